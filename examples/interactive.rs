@@ -0,0 +1,90 @@
+//! Prompt-driven scanning, handy for trying a scanner by hand.
+//!
+//! Run with `cargo run --example interactive`.
+
+use wia_rs::{ScanFormat, ScanOptions, ScanSource, WIAScanManager, WiaError};
+
+fn read_input() -> String {
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
+
+fn main() -> Result<(), WiaError> {
+    let manager = WIAScanManager::init()?;
+
+    println!("Scanning for WIA devices...");
+    let devices = manager.enumerate_devices()?;
+    println!("Found {} WIA device(s)", devices.len());
+
+    for (i, device) in devices.iter().enumerate() {
+        println!("Device {}: ID = {}", i + 1, device.id);
+        println!("      Name = {}", device.name);
+        println!("      Description = {}", device.description);
+        println!(); // Add empty line between devices
+    }
+
+    // Check if any devices were found
+    if devices.is_empty() {
+        println!("No scanner device found");
+        return Ok(());
+    }
+
+    println!("Would you like to scan a document? (y/n)");
+    if read_input().to_lowercase() != "y" {
+        return Ok(());
+    }
+
+    println!("Enter the device number to use for scanning:");
+    let Some(device) = read_input()
+        .parse::<usize>()
+        .ok()
+        .and_then(|num| num.checked_sub(1))
+        .and_then(|index| devices.get(index))
+    else {
+        println!("Invalid device number.");
+        return Ok(());
+    };
+
+    // One connection for both the capability check and the scan
+    let scanner = manager.open_device(&device.id)?;
+    let capabilities = scanner.capabilities()?;
+    if !capabilities.detected() {
+        println!("The driver didn't report its capabilities, offering every source.");
+    }
+    // Sources the driver didn't report on are offered too
+    let feeder = capabilities.feeder != Some(false);
+    let flatbed = capabilities.flatbed != Some(false);
+    let duplex = capabilities.duplex == Some(true);
+
+    let source = if feeder && flatbed {
+        println!("Select scan source:");
+        println!("1. Flatbed");
+        println!("2. Document Feeder");
+        if duplex {
+            println!("3. Document Feeder (duplex)");
+        }
+
+        // Default to flatbed for any other input
+        match read_input().as_str() {
+            "2" => ScanSource::Feeder,
+            "3" if duplex => ScanSource::FeederDuplex,
+            _ => ScanSource::Flatbed,
+        }
+    } else if feeder {
+        println!("Only document feeder available. Using feeder.");
+        ScanSource::Feeder
+    } else {
+        println!("Only flatbed available. Using flatbed.");
+        ScanSource::Flatbed
+    };
+
+    println!("Starting scan with {:?} source...", source);
+    let options = ScanOptions::new()
+        .source(source)
+        .format(ScanFormat::Bmp)
+        .output("scanned_document.bmp");
+    let result = scanner.scan_with_options(&options)?;
+    println!("Scan complete! Document saved as: {}", result.path.display());
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::sync::mpsc;
+use std::thread;
+
+use crate::backend::{BackendDevice, ItemLevel};
+use crate::com::ComThreadingModel;
+use crate::options::{ScanOptions, ScanResult};
+use crate::util::WiaValue;
+use crate::{DeviceId, DeviceInfo, Result, WIAScanManager, WiaError};
+
+type Job = Box<dyn FnOnce(&WIAScanManager) + Send>;
+
+/// A `Send + Sync` handle to a [`WIAScanManager`] living on a thread of its
+/// own.
+///
+/// The manager and every device opened through it stay on the agent's
+/// single-threaded apartment, and calls are sent to it over a channel, so
+/// the handle can be shared between threads freely. Calls from several
+/// threads run one after another, in the order they arrive.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use wia_rs::{ScanOptions, ScanSource, WiaAgent};
+///
+/// let agent = Arc::new(WiaAgent::new()?);
+/// let scanner = std::thread::spawn({
+///     let agent = Arc::clone(&agent);
+///     move || {
+///         let device = agent.default_device()?;
+///         let options = ScanOptions::new().source(ScanSource::Flatbed).output("scan.png");
+///         agent.scan(device.id, options)
+///     }
+/// });
+/// let result = scanner.join().unwrap()?;
+/// # Ok::<(), wia_rs::WiaError>(())
+/// ```
+pub struct WiaAgent {
+    // Taken on drop, which is what tells the thread to finish
+    jobs: Option<mpsc::Sender<Job>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl WiaAgent {
+    /// Starts the agent thread and initializes COM and the manager on it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the thread can't be started or the manager can't be
+    /// initialized on it, see [`WIAScanManager::init_with`].
+    pub fn new() -> Result<Self> {
+        let (jobs_tx, jobs_rx) = mpsc::channel::<Job>();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name("wia-agent".to_string())
+            .spawn(move || {
+                let manager = match WIAScanManager::init_with(ComThreadingModel::SingleThreaded) {
+                    Ok(manager) => manager,
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+
+                // Ends once the handle is dropped and the queue drained
+                for job in jobs_rx {
+                    job(&manager);
+                }
+                log::debug!("WIA agent shutting down");
+            })
+            .map_err(|e| WiaError::Io(format!("Failed to start the agent thread: {}", e)))?;
+
+        // The thread always reports back before it could panic
+        ready_rx.recv().map_err(|_| stopped())??;
+
+        Ok(WiaAgent {
+            jobs: Some(jobs_tx),
+            thread: Some(thread),
+        })
+    }
+
+    /// Runs `work` with the agent's manager on the agent thread and waits
+    /// for its result, for anything the other methods don't cover.
+    ///
+    /// A panic in `work` is resumed on the calling thread, and the agent
+    /// carries on with the next call.
+    pub fn run<T, F>(&self, work: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&WIAScanManager) -> Result<T> + Send + 'static,
+    {
+        let (result_tx, result_rx) = mpsc::channel();
+        let job: Job = Box::new(move |manager| {
+            let result =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| work(manager)));
+            let _ = result_tx.send(result);
+        });
+
+        self.jobs
+            .as_ref()
+            .ok_or_else(stopped)?
+            .send(job)
+            .map_err(|_| stopped())?;
+
+        match result_rx.recv().map_err(|_| stopped())? {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// See [`WIAScanManager::enumerate_devices`].
+    pub fn enumerate_devices(&self) -> Result<Vec<DeviceInfo>> {
+        self.run(|manager| manager.enumerate_devices())
+    }
+
+    /// See [`WIAScanManager::default_device`].
+    pub fn default_device(&self) -> Result<DeviceInfo> {
+        self.run(|manager| manager.default_device())
+    }
+
+    /// Connects to the device and scans a document with the given options.
+    /// See [`WIAScanManager::scan_with_options`].
+    pub fn scan(&self, device_id: impl Into<DeviceId>, options: ScanOptions) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        self.run(move |manager| manager.scan_with_options(device_id, &options))
+    }
+
+    /// Connects to the device and reads a property from its root or scan
+    /// item.
+    pub fn read_property(
+        &self,
+        device_id: impl Into<DeviceId>,
+        level: ItemLevel,
+        prop_id: u32,
+    ) -> Result<WiaValue> {
+        let device_id = device_id.into();
+        self.run(move |manager| manager.open_device(device_id)?.read_property(level, prop_id))
+    }
+}
+
+impl Drop for WiaAgent {
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+        // Waits for a scan in progress, so COM is torn down before we return
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn stopped() -> WiaError {
+    WiaError::Io("The WIA agent thread has stopped".to_string())
+}
@@ -0,0 +1,332 @@
+use std::path::Path;
+use std::time::Duration;
+
+use windows::Win32::Devices::ImageAcquisition::*;
+use windows::core::{GUID, HRESULT, Interface};
+
+use crate::device;
+use crate::document;
+use crate::options::ScanOptions;
+use crate::settings::validate_i4;
+use crate::util::{self, WiaValue, write_guid_property, write_i4_property};
+use crate::{
+    DeviceId, DeviceInfo, DeviceType, FeederStatus, Result, WIAScanManager, WiaDevice, WiaError,
+};
+
+/// Which item of a device a property is read from or written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ItemLevel {
+    /// The root item, which holds the device-wide `WIA_DPS_*` properties.
+    Root,
+    /// The item scans are transferred from.
+    Scan,
+}
+
+/// The calls the crate makes into WIA, so code written against them can
+/// run with something other than the WIA service behind it.
+///
+/// [`WIAScanManager`] implements it with the real service. With the
+/// `testing` feature, `MockBackend` stands in with canned devices and
+/// images, for exercising scanning code without a scanner attached.
+pub trait WiaBackend {
+    /// A connected device, as returned by
+    /// [`create_device`](Self::create_device).
+    type Device<'a>: BackendDevice
+    where
+        Self: 'a;
+
+    fn enumerate_devices(&self) -> Result<Vec<DeviceInfo>>;
+
+    /// Connects to the device with the given ID.
+    fn create_device(&self, device_id: &DeviceId) -> Result<Self::Device<'_>>;
+
+    /// The only scanner, see [`WIAScanManager::default_device`].
+    fn default_device(&self) -> Result<DeviceInfo> {
+        let mut scanners = self.enumerate_devices()?;
+        scanners.retain(|device| device.device_type == DeviceType::Scanner);
+
+        match scanners.len() {
+            0 => Err(WiaError::NoDeviceAvailable),
+            1 => Ok(scanners.remove(0)),
+            _ => Err(WiaError::MultipleDevices(scanners)),
+        }
+    }
+
+    /// Every device whose name contains `pattern`, ignoring case.
+    fn find_devices_by_name(&self, pattern: &str) -> Result<Vec<DeviceInfo>> {
+        let pattern = pattern.to_lowercase();
+        let mut devices = self.enumerate_devices()?;
+        devices.retain(|device| device.name.to_lowercase().contains(&pattern));
+        Ok(devices)
+    }
+}
+
+/// A device connected through a [`WiaBackend`].
+///
+/// Implementations provide raw property access and transfers; applying
+/// [`ScanOptions`] is built on top of those and shared by every backend.
+pub trait BackendDevice {
+    fn read_property(&self, level: ItemLevel, prop_id: u32) -> Result<WiaValue>;
+
+    fn write_property(&self, level: ItemLevel, prop_id: u32, value: i32) -> Result<()>;
+
+    fn write_guid_property(&self, level: ItemLevel, prop_id: u32, value: GUID) -> Result<()>;
+
+    /// The access flags of a property and its valid values, laid out as
+    /// `GetPropertyAttributes` reports them: `[min, nominal, max, step]`
+    /// for `WIA_PROP_RANGE`, `[count, nominal, values...]` for
+    /// `WIA_PROP_LIST`.
+    fn property_attributes(&self, level: ItemLevel, prop_id: u32) -> Result<(u32, Vec<i32>)>;
+
+    /// Transfers a single image from the scan item, as currently
+    /// configured, into memory. [`transfer`](Self::transfer) has switched
+    /// the item to `TYMED_CALLBACK` beforehand.
+    fn transfer_image(&self) -> Result<Vec<u8>>;
+
+    /// Transfers a single image from the scan item into the file at
+    /// `path`. Returns the success code, since `S_FALSE` and
+    /// `WIA_STATUS_END_OF_MEDIA` tell multi-page scans when to stop.
+    fn transfer_file(&self, path: &Path) -> Result<HRESULT>;
+
+    /// Makes the child item named `name` the scan item, see
+    /// [`ScanOptions::item`]. Backends without named items reject every
+    /// name.
+    fn select_item(&self, name: &str) -> Result<()> {
+        Err(WiaError::ItemNotFound(name.to_string()))
+    }
+
+    /// One item's properties, for the `set_*` functions.
+    fn item(&self, level: ItemLevel) -> BackendItem<'_, Self> {
+        BackendItem { device: self, level }
+    }
+
+    /// Applies `options` and transfers a single image into memory.
+    fn transfer(&self, options: &ScanOptions) -> Result<Vec<u8>> {
+        options.validate()?;
+        let configured = device::prepare_banded(self, options)?;
+        let data = self.transfer_image();
+        device::restore_tymed(self, configured.previous_tymed);
+        let data = data?;
+        if configured.bmp_to_pdf {
+            return Ok(document::encode_pdf(&[document::decode_bmp(&data)?]));
+        }
+        Ok(data)
+    }
+
+    /// Reads a property that has to be a `VT_I4`.
+    fn read_i4(&self, level: ItemLevel, prop_id: u32) -> Result<i32> {
+        match self.read_property(level, prop_id)? {
+            WiaValue::I4(value) => Ok(value),
+            other => Err(WiaError::UnsupportedPropertyType(other.var_type())),
+        }
+    }
+
+    /// See [`WiaDevice::feeder_status`].
+    fn feeder_status(&self) -> Result<FeederStatus> {
+        let status = self.read_i4(ItemLevel::Root, WIA_DPS_DOCUMENT_HANDLING_STATUS)? as u32;
+        Ok(FeederStatus {
+            paper_loaded: status & FEED_READY != 0,
+            paper_jam: status & PAPER_JAM != 0,
+            cover_open: status & PATH_COVER_UP != 0,
+        })
+    }
+
+    /// See [`WiaDevice::lamp_state`].
+    fn lamp_state(&self) -> Result<bool> {
+        let item = lamp_item(self, WIA_IPS_LAMP, "Lamp control")?;
+        Ok(item.read_i4(WIA_IPS_LAMP)? as u32 == WIA_LAMP_ON)
+    }
+
+    /// See [`WiaDevice::set_lamp`].
+    fn set_lamp(&self, on: bool) -> Result<()> {
+        let item = lamp_item(self, WIA_IPS_LAMP, "Lamp control")?;
+        let value = if on { WIA_LAMP_ON } else { WIA_LAMP_OFF };
+        item.write_i4(WIA_IPS_LAMP, value as i32)
+    }
+
+    /// See [`WiaDevice::set_lamp_auto_off`].
+    fn set_lamp_auto_off(&self, after: Duration) -> Result<()> {
+        let item = lamp_item(self, WIA_IPS_LAMP_AUTO_OFF, "Lamp auto-off")?;
+        let seconds = after.as_secs().min(i32::MAX as u64) as i32;
+        validate_i4(&item, WIA_IPS_LAMP_AUTO_OFF, "Lamp auto-off", seconds)?;
+        item.write_i4(WIA_IPS_LAMP_AUTO_OFF, seconds)
+    }
+}
+
+/// The scan item, if it exposes the lamp property `prop_id`.
+///
+/// The root item is never used: there the same IDs are `WIA_DPS_*`
+/// properties, such as `WIA_DPS_MIN_VERTICAL_SHEET_FEED_SIZE` for
+/// `WIA_IPS_LAMP`, that have nothing to do with the lamp.
+fn lamp_item<'a, D: BackendDevice + ?Sized>(
+    device: &'a D,
+    prop_id: u32,
+    feature: &str,
+) -> Result<BackendItem<'a, D>> {
+    let item = device.item(ItemLevel::Scan);
+    match item.read_property(prop_id) {
+        Ok(WiaValue::I4(_)) => Ok(item),
+        _ => Err(WiaError::FeatureUnsupported(feature.to_string())),
+    }
+}
+
+impl<D: BackendDevice + ?Sized> BackendDevice for &D {
+    fn read_property(&self, level: ItemLevel, prop_id: u32) -> Result<WiaValue> {
+        (**self).read_property(level, prop_id)
+    }
+
+    fn write_property(&self, level: ItemLevel, prop_id: u32, value: i32) -> Result<()> {
+        (**self).write_property(level, prop_id, value)
+    }
+
+    fn write_guid_property(&self, level: ItemLevel, prop_id: u32, value: GUID) -> Result<()> {
+        (**self).write_guid_property(level, prop_id, value)
+    }
+
+    fn property_attributes(&self, level: ItemLevel, prop_id: u32) -> Result<(u32, Vec<i32>)> {
+        (**self).property_attributes(level, prop_id)
+    }
+
+    fn transfer_image(&self) -> Result<Vec<u8>> {
+        (**self).transfer_image()
+    }
+
+    fn transfer_file(&self, path: &Path) -> Result<HRESULT> {
+        (**self).transfer_file(path)
+    }
+
+    fn select_item(&self, name: &str) -> Result<()> {
+        (**self).select_item(name)
+    }
+}
+
+/// The properties of a single WIA item, which the `set_*` functions read
+/// and write.
+///
+/// Implemented for `IWiaItem` and `IWiaPropertyStorage`, and for an item of
+/// any [`BackendDevice`] through [`BackendDevice::item`].
+pub trait ItemProperties {
+    fn read_property(&self, prop_id: u32) -> Result<WiaValue>;
+
+    fn write_i4(&self, prop_id: u32, value: i32) -> Result<()>;
+
+    fn write_guid(&self, prop_id: u32, value: GUID) -> Result<()>;
+
+    /// See [`BackendDevice::property_attributes`].
+    fn property_attributes(&self, prop_id: u32) -> Result<(u32, Vec<i32>)>;
+
+    /// Reads a property that has to be a `VT_I4`.
+    fn read_i4(&self, prop_id: u32) -> Result<i32> {
+        match self.read_property(prop_id)? {
+            WiaValue::I4(value) => Ok(value),
+            other => Err(WiaError::UnsupportedPropertyType(other.var_type())),
+        }
+    }
+}
+
+impl ItemProperties for IWiaPropertyStorage {
+    fn read_property(&self, prop_id: u32) -> Result<WiaValue> {
+        util::read_property(self, prop_id)
+    }
+
+    fn write_i4(&self, prop_id: u32, value: i32) -> Result<()> {
+        write_i4_property(self, prop_id, value)
+    }
+
+    fn write_guid(&self, prop_id: u32, value: GUID) -> Result<()> {
+        write_guid_property(self, prop_id, value)
+    }
+
+    fn property_attributes(&self, prop_id: u32) -> Result<(u32, Vec<i32>)> {
+        util::read_property_attributes(self, prop_id)
+    }
+
+    fn read_i4(&self, prop_id: u32) -> Result<i32> {
+        util::read_i4_property(self, prop_id)
+    }
+}
+
+impl ItemProperties for IWiaItem {
+    fn read_property(&self, prop_id: u32) -> Result<WiaValue> {
+        self.cast::<IWiaPropertyStorage>()?.read_property(prop_id)
+    }
+
+    fn write_i4(&self, prop_id: u32, value: i32) -> Result<()> {
+        self.cast::<IWiaPropertyStorage>()?.write_i4(prop_id, value)
+    }
+
+    fn write_guid(&self, prop_id: u32, value: GUID) -> Result<()> {
+        self.cast::<IWiaPropertyStorage>()?.write_guid(prop_id, value)
+    }
+
+    fn property_attributes(&self, prop_id: u32) -> Result<(u32, Vec<i32>)> {
+        self.cast::<IWiaPropertyStorage>()?.property_attributes(prop_id)
+    }
+}
+
+/// One item of a [`BackendDevice`], from [`BackendDevice::item`].
+pub struct BackendItem<'a, D: ?Sized> {
+    device: &'a D,
+    level: ItemLevel,
+}
+
+impl<D: BackendDevice + ?Sized> ItemProperties for BackendItem<'_, D> {
+    fn read_property(&self, prop_id: u32) -> Result<WiaValue> {
+        self.device.read_property(self.level, prop_id)
+    }
+
+    fn write_i4(&self, prop_id: u32, value: i32) -> Result<()> {
+        self.device.write_property(self.level, prop_id, value)
+    }
+
+    fn write_guid(&self, prop_id: u32, value: GUID) -> Result<()> {
+        self.device.write_guid_property(self.level, prop_id, value)
+    }
+
+    fn property_attributes(&self, prop_id: u32) -> Result<(u32, Vec<i32>)> {
+        self.device.property_attributes(self.level, prop_id)
+    }
+}
+
+impl WiaBackend for WIAScanManager {
+    type Device<'a> = WiaDevice<'a>;
+
+    fn enumerate_devices(&self) -> Result<Vec<DeviceInfo>> {
+        WIAScanManager::enumerate_devices(self)
+    }
+
+    fn create_device(&self, device_id: &DeviceId) -> Result<WiaDevice<'_>> {
+        self.open_device(device_id)
+    }
+}
+
+impl BackendDevice for WiaDevice<'_> {
+    fn read_property(&self, level: ItemLevel, prop_id: u32) -> Result<WiaValue> {
+        util::read_property(&self.item_properties(level)?, prop_id)
+    }
+
+    fn write_property(&self, level: ItemLevel, prop_id: u32, value: i32) -> Result<()> {
+        write_i4_property(&self.item_properties(level)?, prop_id, value)
+    }
+
+    fn write_guid_property(&self, level: ItemLevel, prop_id: u32, value: GUID) -> Result<()> {
+        write_guid_property(&self.item_properties(level)?, prop_id, value)
+    }
+
+    fn property_attributes(&self, level: ItemLevel, prop_id: u32) -> Result<(u32, Vec<i32>)> {
+        util::read_property_attributes(&self.item_properties(level)?, prop_id)
+    }
+
+    fn transfer_image(&self) -> Result<Vec<u8>> {
+        self.transfer_banded()
+    }
+
+    fn transfer_file(&self, path: &Path) -> Result<HRESULT> {
+        let data_transfer: IWiaDataTransfer = self.scan_item()?.cast()?;
+        device::transfer_to_file(&data_transfer, path, None)
+    }
+
+    fn select_item(&self, name: &str) -> Result<()> {
+        WiaDevice::select_item(self, name)
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use windows::{
+    Win32::{Devices::ImageAcquisition::*, Foundation::S_FALSE},
+    core::*,
+};
+
+use crate::DeviceId;
+use crate::events::{DeviceEvent, DeviceEventKind};
+
+/// Collects the bands a driver sends during `idtGetBandedData` into a
+/// single buffer. Like [`ProgressCallback`], it may be called on WIA's RPC
+/// threads, hence the mutex.
+#[implement(IWiaDataCallback)]
+#[derive(Default)]
+pub(crate) struct MemoryCallback {
+    data: Mutex<Vec<u8>>,
+}
+
+impl MemoryCallback {
+    /// Hands over everything received so far.
+    pub(crate) fn take_data(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.data.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl IWiaDataCallback_Impl for MemoryCallback_Impl {
+    fn BandedDataCallback(
+        &self,
+        lmessage: i32,
+        _lstatus: i32,
+        _lpercentcomplete: i32,
+        loffset: i32,
+        llength: i32,
+        _lreserved: i32,
+        _lreslength: i32,
+        pbbuffer: *mut u8,
+    ) -> Result<()> {
+        match lmessage as u32 {
+            IT_MSG_DATA_HEADER if !pbbuffer.is_null() => {
+                // The header carries the expected total size, when the driver knows it
+                let header = unsafe { &*(pbbuffer as *const WIA_DATA_CALLBACK_HEADER) };
+                if header.lBufferSize > 0 {
+                    let mut data = self.data.lock().unwrap_or_else(|e| e.into_inner());
+                    data.reserve(header.lBufferSize as usize);
+                }
+            }
+            IT_MSG_DATA if !pbbuffer.is_null() && llength > 0 => {
+                let band = unsafe { std::slice::from_raw_parts(pbbuffer, llength as usize) };
+                let offset = loffset.max(0) as usize;
+                let end = offset + band.len();
+
+                // Bands normally arrive in order, but the offset is authoritative
+                let mut data = self.data.lock().unwrap_or_else(|e| e.into_inner());
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[offset..end].copy_from_slice(band);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+type ProgressFn = Box<dyn FnMut(u32, &[u8]) + Send>;
+
+/// Forwards transfer progress to a user closure, along with any band of
+/// data the driver hands over with it. In a multithreaded apartment WIA
+/// calls in on its RPC threads, hence the mutex.
+#[implement(IWiaDataCallback)]
+pub(crate) struct ProgressCallback {
+    progress: Mutex<ProgressFn>,
+}
+
+impl ProgressCallback {
+    pub(crate) fn new(progress: impl FnMut(u32, &[u8]) + Send + 'static) -> Self {
+        ProgressCallback {
+            progress: Mutex::new(Box::new(progress)),
+        }
+    }
+}
+
+impl IWiaDataCallback_Impl for ProgressCallback_Impl {
+    fn BandedDataCallback(
+        &self,
+        lmessage: i32,
+        _lstatus: i32,
+        lpercentcomplete: i32,
+        _loffset: i32,
+        llength: i32,
+        _lreserved: i32,
+        _lreslength: i32,
+        pbbuffer: *mut u8,
+    ) -> Result<()> {
+        let percent = lpercentcomplete.clamp(0, 100) as u32;
+        let mut progress = self.progress.lock().unwrap_or_else(|e| e.into_inner());
+        match lmessage as u32 {
+            IT_MSG_STATUS => (progress)(percent, &[]),
+            IT_MSG_DATA if !pbbuffer.is_null() && llength > 0 => {
+                let band = unsafe { std::slice::from_raw_parts(pbbuffer, llength as usize) };
+                (progress)(percent, band);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Cancels the transfer it is passed to once `cancelled` is set. Drivers
+/// check in with the callback between bands, so cancelling takes effect at
+/// the next one.
+#[implement(IWiaDataCallback)]
+pub(crate) struct CancelCallback {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelCallback {
+    pub(crate) fn new(cancelled: Arc<AtomicBool>) -> Self {
+        CancelCallback { cancelled }
+    }
+}
+
+impl IWiaDataCallback_Impl for CancelCallback_Impl {
+    fn BandedDataCallback(
+        &self,
+        _lmessage: i32,
+        _lstatus: i32,
+        _lpercentcomplete: i32,
+        _loffset: i32,
+        _llength: i32,
+        _lreserved: i32,
+        _lreslength: i32,
+        _pbbuffer: *mut u8,
+    ) -> Result<()> {
+        // S_FALSE is how a callback asks the driver to stop
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(Error::from_hresult(S_FALSE));
+        }
+        Ok(())
+    }
+}
+
+type EventFn = Box<dyn FnMut(DeviceEvent) + Send>;
+
+/// Forwards device events to a user closure. WIA may call in from any RPC
+/// thread, hence the mutex.
+#[implement(IWiaEventCallback)]
+pub(crate) struct EventCallback {
+    on_event: Mutex<EventFn>,
+}
+
+impl EventCallback {
+    pub(crate) fn new(on_event: impl FnMut(DeviceEvent) + Send + 'static) -> Self {
+        EventCallback {
+            on_event: Mutex::new(Box::new(on_event)),
+        }
+    }
+}
+
+impl IWiaEventCallback_Impl for EventCallback_Impl {
+    fn ImageEventCallback(
+        &self,
+        peventguid: *const GUID,
+        _bstreventdescription: &BSTR,
+        bstrdeviceid: &BSTR,
+        _bstrdevicedescription: &BSTR,
+        _dwdevicetype: u32,
+        _bstrfullitemname: &BSTR,
+        _puleventtype: *mut u32,
+        _ulreserved: u32,
+    ) -> Result<()> {
+        if peventguid.is_null() {
+            return Ok(());
+        }
+
+        let kind = match unsafe { *peventguid } {
+            WIA_EVENT_DEVICE_CONNECTED => DeviceEventKind::Connected,
+            WIA_EVENT_DEVICE_DISCONNECTED => DeviceEventKind::Disconnected,
+            WIA_EVENT_SCAN_IMAGE => DeviceEventKind::ScanButton,
+            _ => return Ok(()),
+        };
+        let event = DeviceEvent {
+            kind,
+            device_id: DeviceId::from(bstrdeviceid),
+        };
+
+        // A panic in a previous call shouldn't silence every later event
+        let mut on_event = self.on_event.lock().unwrap_or_else(|e| e.into_inner());
+        (on_event)(event);
+        Ok(())
+    }
+}
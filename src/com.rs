@@ -0,0 +1,110 @@
+use std::cell::Cell;
+
+use windows::Win32::{Foundation::RPC_E_CHANGED_MODE, System::Com::*};
+
+use crate::Result;
+
+/// The COM apartment model to initialize the calling thread with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ComThreadingModel {
+    /// A single-threaded apartment, as used by GUI threads.
+    SingleThreaded,
+    #[default]
+    MultiThreaded,
+}
+
+impl ComThreadingModel {
+    fn coinit(self) -> COINIT {
+        match self {
+            ComThreadingModel::SingleThreaded => COINIT_APARTMENTTHREADED,
+            ComThreadingModel::MultiThreaded => COINIT_MULTITHREADED,
+        }
+    }
+}
+
+thread_local! {
+    // How many live managers on this thread share our CoInitializeEx call
+    static COM_REFS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Initializes COM for the calling thread unless a manager on this thread
+/// already did. Returns whether the caller holds a reference that must be
+/// released with [`release`].
+///
+/// `false` means the thread was already set up with another apartment model
+/// by someone else, so the apartment isn't ours to tear down.
+pub(crate) fn acquire(model: ComThreadingModel) -> Result<bool> {
+    COM_REFS.with(|refs| {
+        if refs.get() == 0 {
+            // S_FALSE (already initialized on this thread) still has to be
+            // balanced with CoUninitialize, RPC_E_CHANGED_MODE must not be
+            let hr = unsafe { CoInitializeEx(None, model.coinit()) };
+            if hr == RPC_E_CHANGED_MODE {
+                return Ok(false);
+            }
+            hr.ok()?;
+        }
+
+        refs.set(refs.get() + 1);
+        Ok(true)
+    })
+}
+
+/// Releases a reference taken by [`acquire`], uninitializing COM once the
+/// last manager on this thread is gone.
+pub(crate) fn release() {
+    COM_REFS.with(|refs| {
+        let remaining = refs.get().saturating_sub(1);
+        refs.set(remaining);
+        if remaining == 0 {
+            unsafe { CoUninitialize() };
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each test runs on its own thread, so every one starts from zero
+
+    fn refs() -> usize {
+        COM_REFS.with(Cell::get)
+    }
+
+    #[test]
+    fn nested_acquires_share_one_initialization() {
+        assert_eq!(acquire(ComThreadingModel::MultiThreaded), Ok(true));
+        assert_eq!(acquire(ComThreadingModel::MultiThreaded), Ok(true));
+        assert_eq!(refs(), 2);
+
+        release();
+        assert_eq!(refs(), 1);
+        release();
+        assert_eq!(refs(), 0);
+    }
+
+    #[test]
+    fn a_second_model_on_the_same_thread_is_not_ours_to_release() {
+        unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }.unwrap();
+
+        // RPC_E_CHANGED_MODE: the thread's apartment belongs to someone else
+        assert_eq!(acquire(ComThreadingModel::MultiThreaded), Ok(false));
+        assert_eq!(refs(), 0);
+
+        unsafe { CoUninitialize() };
+    }
+
+    #[test]
+    fn an_existing_apartment_of_the_same_model_is_still_counted() {
+        unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.unwrap();
+
+        // S_FALSE still takes a reference that release has to balance
+        assert_eq!(acquire(ComThreadingModel::MultiThreaded), Ok(true));
+        assert_eq!(refs(), 1);
+        release();
+        assert_eq!(refs(), 0);
+
+        unsafe { CoUninitialize() };
+    }
+}
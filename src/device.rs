@@ -0,0 +1,2248 @@
+use std::cell::RefCell;
+use std::marker::PhantomData;
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use windows::{
+    Win32::{
+        Devices::ImageAcquisition::*,
+        Foundation::{E_INVALIDARG, S_FALSE},
+        System::Com::*,
+    },
+    core::*,
+};
+
+use crate::backend::{BackendDevice, ItemLevel, ItemProperties};
+use crate::callback::{MemoryCallback, ProgressCallback};
+use crate::com::{self, ComThreadingModel};
+use crate::document;
+use crate::options::{
+    AutoCrop, ColorMode, DocumentFormat, EffectiveSettings, Level, PageSize, RotationProperty,
+    ScanFormat, ScanOptions, ScanResult, ScanSource,
+};
+use crate::error::{SettingError, WiaStatus};
+use crate::{DeviceId, Result, WIAScanManager, WiaError};
+use crate::settings::{
+    set_auto_crop, set_auto_exposure, set_bit_depth, set_blank_page_skipping, set_brightness,
+    set_brightness_normalized, set_color_mode_with_intent, set_compression, set_contrast,
+    set_contrast_normalized, set_deskew, set_intent, set_multi_feed_detection, set_orientation,
+    set_page_size, set_preview, set_resolution, set_resolution_xy, set_rotation, set_scan_region,
+    set_threshold, supported_resolutions, validate_i4,
+};
+use crate::util::{
+    WiaValue, read_bstr_property, read_i4_property, read_optional_bstr_property, read_property,
+    write_guid_property, write_i4_property,
+};
+
+/// An open connection to a WIA device.
+///
+/// The handle borrows the [`WIAScanManager`] it was opened from, so the COM
+/// references it owns are always released before `CoUninitialize` runs.
+pub struct WiaDevice<'m> {
+    item: IWiaItem,
+    props: IWiaPropertyStorage,
+    id: DeviceId,
+    name: String,
+    description: String,
+    vendor: Option<String>,
+    serial_number: Option<String>,
+    firmware_version: Option<String>,
+    // The child item scans come from, found on first use
+    scan_item: RefCell<Option<IWiaItem>>,
+    _manager: PhantomData<&'m WIAScanManager>,
+}
+
+impl<'m> WiaDevice<'m> {
+    pub(crate) fn connect(
+        device_manager: &IWiaDevMgr,
+        device_id: &DeviceId,
+    ) -> Result<Self> {
+        let item = create_device(device_manager, device_id)?;
+        Self::from_item(item, device_id)
+    }
+
+    /// Like [`connect`](Self::connect), but gives up after `timeout`.
+    ///
+    /// `CreateDevice` can't be cancelled, so it runs on a worker thread with
+    /// its own COM initialization and device manager. On timeout the worker
+    /// is left to finish the call and clean up after itself.
+    pub(crate) fn connect_with_timeout(device_id: &DeviceId, timeout: Duration) -> Result<Self> {
+        let (item_tx, item_rx) = mpsc::channel();
+        // Keeps the worker's apartment alive until we've resolved the item
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+
+        let worker_id = device_id.clone();
+        std::thread::spawn(move || {
+            let owns_com = match com::acquire(ComThreadingModel::MultiThreaded) {
+                Ok(owns_com) => owns_com,
+                Err(e) => {
+                    let _ = item_tx.send(Err(e));
+                    return;
+                }
+            };
+
+            // The manager's own IWiaDevMgr can't be used here: calls on it
+            // would be marshaled back to the caller's thread, which sits in
+            // recv_timeout and, in an STA, never pumps them
+            let result = unsafe { CoCreateInstance(&WiaDevMgr, None, CLSCTX_LOCAL_SERVER) }
+                .map_err(WiaError::from)
+                .and_then(|device_manager: IWiaDevMgr| create_device(&device_manager, &worker_id))
+                .and_then(|item| AgileReference::new(&item).map_err(WiaError::from));
+
+            // Nobody is listening any more if the caller timed out
+            if item_tx.send(result).is_ok() {
+                let _ = done_rx.recv();
+            }
+
+            if owns_com {
+                com::release();
+            }
+        });
+
+        let item = match item_rx.recv_timeout(timeout) {
+            Ok(result) => result?.resolve(),
+            Err(_) => {
+                log::warn!("Timed out connecting to device: {}", device_id);
+                return Err(WiaError::Timeout);
+            }
+        };
+        drop(done_tx);
+
+        Self::from_item(item?, device_id)
+    }
+
+    fn from_item(item: IWiaItem, device_id: &DeviceId) -> Result<Self> {
+        let props: IWiaPropertyStorage = item.cast()?;
+        // Read once up front so callers who reconnect from a stored ID
+        // don't have to enumerate to show what they're connected to
+        let name = read_optional_bstr_property(&props, WIA_DIP_DEV_NAME).unwrap_or_default();
+        let description =
+            read_optional_bstr_property(&props, WIA_DIP_DEV_DESC).unwrap_or_default();
+        let (vendor, serial_number, firmware_version) = read_hardware_info(&props);
+
+        Ok(WiaDevice {
+            item,
+            props,
+            id: device_id.clone(),
+            name,
+            description,
+            vendor,
+            serial_number,
+            firmware_version,
+            scan_item: RefCell::new(None),
+            _manager: PhantomData,
+        })
+    }
+
+    /// The ID the device was opened with.
+    pub fn id(&self) -> &DeviceId {
+        &self.id
+    }
+
+    /// The device's friendly name, as read when it was opened.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The device's description, as read when it was opened.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The manufacturer's name (`WIA_DIP_VEND_DESC`), if the driver
+    /// reports one.
+    pub fn vendor(&self) -> Option<&str> {
+        self.vendor.as_deref()
+    }
+
+    /// The device's serial number, if the driver reports one. Only WSD
+    /// network scanners have a standard property for it
+    /// (`WIA_WSD_SERIAL_NUMBER`).
+    pub fn serial_number(&self) -> Option<&str> {
+        self.serial_number.as_deref()
+    }
+
+    /// The firmware version (`WIA_DPA_FIRMWARE_VERSION`), if the driver
+    /// reports one.
+    pub fn firmware_version(&self) -> Option<&str> {
+        self.firmware_version.as_deref()
+    }
+
+    /// The property storage of the device's root item.
+    pub fn properties(&self) -> &IWiaPropertyStorage {
+        &self.props
+    }
+
+    /// The device's root `IWiaItem`, for vendor-specific calls the crate
+    /// doesn't wrap.
+    ///
+    /// The interface is only valid while the [`WIAScanManager`] this device
+    /// was opened from is alive. Don't clone it and keep it past the
+    /// manager: once the manager is dropped COM may be uninitialized on this
+    /// thread, and any call through the interface is undefined behaviour.
+    #[cfg(feature = "unsafe_raw")]
+    pub fn raw_item(&self) -> &IWiaItem {
+        &self.item
+    }
+
+    /// A new reference to the root item's `IWiaPropertyStorage`, for
+    /// property writes the crate doesn't wrap.
+    ///
+    /// The same lifetime rules as for [`raw_item`](Self::raw_item) apply:
+    /// drop it before the manager.
+    #[cfg(feature = "unsafe_raw")]
+    pub fn raw_properties(&self) -> Result<IWiaPropertyStorage> {
+        self.item.cast().map_err(WiaError::from)
+    }
+
+    /// Re-reads the cached name, description and hardware details and
+    /// forgets the cached scan item, for when the driver's configuration
+    /// changed underneath.
+    pub fn refresh(&mut self) -> Result<()> {
+        self.name = read_bstr_property(&self.props, WIA_DIP_DEV_NAME)?;
+        self.description = read_bstr_property(&self.props, WIA_DIP_DEV_DESC)?;
+        (self.vendor, self.serial_number, self.firmware_version) =
+            read_hardware_info(&self.props);
+        self.scan_item.replace(None);
+        Ok(())
+    }
+
+    /// Drops the connection and opens the device again by its ID, for when
+    /// it went offline and came back, as USB scanners sometimes do
+    /// mid-session.
+    ///
+    /// The scan item is looked up again on next use, so any settings made
+    /// on it directly have to be applied again.
+    pub fn reconnect(&mut self) -> Result<()> {
+        log::info!("Reconnecting to device: {}", self.id);
+        self.scan_item.replace(None);
+
+        // The manager's IWiaDevMgr isn't kept, but it's alive for 'm, so COM
+        // is still initialized on this thread and another one can be made
+        let device_manager: IWiaDevMgr =
+            unsafe { CoCreateInstance(&WiaDevMgr, None, CLSCTX_LOCAL_SERVER) }?;
+        let item = create_device(&device_manager, &self.id)?;
+        self.props = item.cast()?;
+        self.item = item;
+        Ok(())
+    }
+
+    /// Runs `operation`, and if it fails because the device went offline or
+    /// stopped answering, [reconnects](Self::reconnect) and runs it once more.
+    ///
+    /// ```no_run
+    /// # use wia_rs::{ScanSource, WIAScanManager};
+    /// let manager = WIAScanManager::init()?;
+    /// let mut device = manager.open_device(manager.default_device()?.id)?;
+    /// let result = device.with_reconnect(|device| device.scan(ScanSource::Flatbed, "scan.bmp"))?;
+    /// # Ok::<(), wia_rs::WiaError>(())
+    /// ```
+    pub fn with_reconnect<T>(
+        &mut self,
+        mut operation: impl FnMut(&Self) -> Result<T>,
+    ) -> Result<T> {
+        match operation(self) {
+            Err(e @ (WiaError::Offline | WiaError::DeviceCommunication)) => {
+                log::warn!("Device dropped ({}), reconnecting once", e);
+                self.reconnect()?;
+                operation(self)
+            }
+            result => result,
+        }
+    }
+
+    /// Lists the device's child items, for multi-function devices that
+    /// expose the flatbed and the feeder as separate items.
+    pub fn items(&self) -> Result<Vec<WiaItemInfo>> {
+        child_items(&self.item)?.iter().map(item_info).collect()
+    }
+
+    /// The device's whole item tree below the root, for cameras and other
+    /// devices that keep items in folders, descending into every folder.
+    pub fn item_tree(&self) -> Result<Vec<WiaItemNode<'m>>> {
+        walk_items(&self.item)
+    }
+
+    /// Makes the child item named `name` the one scans come from, instead
+    /// of the first one. It stays selected until [`refresh`](Self::refresh)
+    /// or [`reconnect`](Self::reconnect) is called.
+    ///
+    /// Fails with [`WiaError::ItemNotFound`] if no child has that name.
+    pub fn select_item(&self, name: &str) -> Result<()> {
+        let item = self.find_item(name)?;
+        log::debug!("Selected scan item: {}", name);
+        self.scan_item.replace(Some(item));
+        Ok(())
+    }
+
+    fn find_item(&self, name: &str) -> Result<IWiaItem> {
+        for item in child_items(&self.item)? {
+            if item_info(&item)?.name == name {
+                return Ok(item);
+            }
+        }
+        Err(WiaError::ItemNotFound(name.to_string()))
+    }
+
+    fn first_child_item(&self) -> Result<Option<IWiaItem>> {
+        unsafe {
+            let enum_items: IEnumWiaItem = self.item.EnumChildItems()?;
+            let mut child_item: Option<IWiaItem> = None;
+            let mut num_fetched: u32 = 0;
+            enum_items.Next(1, &mut child_item, &mut num_fetched)?;
+            Ok(child_item)
+        }
+    }
+
+    /// Detects whether the device has a document feeder, a flatbed and
+    /// duplex support, checking both the device and its scan item.
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        // Find the scanner item
+        self.scan_item()?;
+        log::debug!("Scanner item found successfully");
+
+        capabilities(self)
+    }
+
+    /// The resolutions the scan item accepts, read from the attributes of
+    /// `WIA_IPS_XRES`. Drivers that report neither a list nor a range get
+    /// their current resolution back as the only choice.
+    pub fn supported_resolutions(&self) -> Result<Resolutions> {
+        supported_resolutions(&self.scan_item()?)
+    }
+
+    /// Reads the feeder's current state, so callers can ask for paper to be
+    /// loaded before a scan instead of failing with
+    /// [`WiaError::PaperEmpty`].
+    pub fn feeder_status(&self) -> Result<FeederStatus> {
+        BackendDevice::feeder_status(self)
+    }
+
+    /// Whether the flatbed lamp is on, so callers can warn before a scan
+    /// that has to wait for it to warm up.
+    ///
+    /// Fails with [`WiaError::FeatureUnsupported`] if the driver has no
+    /// lamp control.
+    pub fn lamp_state(&self) -> Result<bool> {
+        BackendDevice::lamp_state(self)
+    }
+
+    /// Turns the flatbed lamp on or off. Turning it on ahead of time saves
+    /// the warm-up at the start of the next scan, and fixes
+    /// [`WiaError::LampOff`].
+    pub fn set_lamp(&self, on: bool) -> Result<()> {
+        BackendDevice::set_lamp(self, on)
+    }
+
+    /// Sets how long the lamp stays on after a scan before the device
+    /// turns it off, rounded down to whole seconds.
+    pub fn set_lamp_auto_off(&self, after: Duration) -> Result<()> {
+        BackendDevice::set_lamp_auto_off(self, after)
+    }
+
+    /// Scans a document from the given source. The handle stays connected
+    /// afterwards and can be used for further scans.
+    pub fn scan(
+        &self,
+        source: ScanSource,
+        output: impl AsRef<Path>,
+    ) -> Result<ScanResult> {
+        self.scan_with_options(&ScanOptions::new().source(source).output(output.as_ref()))
+    }
+
+    /// Scans a document using the given options. Options left unset keep
+    /// whatever the driver is currently configured with.
+    pub fn scan_with_options(&self, options: &ScanOptions) -> Result<ScanResult> {
+        self.scan_with_callback(options, None)
+    }
+
+    /// Like [`scan`](Self::scan), but calls `progress` with the percentage
+    /// complete as the transfer goes. The slice holds any partial image data
+    /// the driver sent along, and is empty for plain status updates.
+    ///
+    /// In a multithreaded apartment, the default, WIA calls `progress` on
+    /// its own RPC threads rather than the one that started the scan.
+    ///
+    /// ```no_run
+    /// # let manager = wia_rs::WIAScanManager::init()?;
+    /// # let device = manager.open_device("")?;
+    /// device.scan_with_progress(wia_rs::ScanSource::Flatbed, "scan.png", |percent, _| {
+    ///     println!("{}%", percent);
+    /// })?;
+    /// # Ok::<(), wia_rs::WiaError>(())
+    /// ```
+    pub fn scan_with_progress(
+        &self,
+        source: ScanSource,
+        output: impl AsRef<Path>,
+        progress: impl FnMut(u32, &[u8]) + Send + 'static,
+    ) -> Result<ScanResult> {
+        let options = ScanOptions::new().source(source).output(output.as_ref());
+        let callback: IWiaDataCallback = ProgressCallback::new(progress).into();
+        self.scan_with_callback(&options, Some(&callback))
+    }
+
+    pub(crate) fn scan_with_callback(
+        &self,
+        options: &ScanOptions,
+        callback: Option<&IWiaDataCallback>,
+    ) -> Result<ScanResult> {
+        options.validate()?;
+
+        let (scan_item, item_props, configured) = self.prepare_item(options)?;
+        let output_path = absolute_output_path(options)?;
+
+        let paths = match transfer_scan(&scan_item, options, &output_path, callback) {
+            Err(WiaError::LampOff) => {
+                // Devices that let us switch the lamp on are worth one more try
+                log::info!("The lamp is off, turning it on and trying again");
+                self.set_lamp(true).map_err(|_| WiaError::LampOff)?;
+                transfer_scan(&scan_item, options, &output_path, callback)?
+            }
+            result => result?,
+        };
+        if configured.bmp_to_pdf {
+            paths.iter().try_for_each(|path| wrap_in_pdf(path))?;
+        }
+        log::info!("Scan complete! Document saved as: {}", output_path.display());
+
+        Ok(configured.apply_to(scan_result(paths, &item_props)))
+    }
+
+    /// Scans a document straight into memory instead of a file, returning
+    /// the encoded image bytes.
+    ///
+    /// The image is transferred in bands through a callback, so not every
+    /// driver accepts every format here; most support at least
+    /// [`ScanFormat::Bmp`].
+    pub fn scan_to_memory(
+        &self,
+        source: ScanSource,
+        format: ScanFormat,
+    ) -> Result<Vec<u8>> {
+        self.transfer_to_memory(&ScanOptions::new().source(source).format(format))
+    }
+
+    /// Scans a quick color preview and returns it as PNG bytes, for showing
+    /// before the full scan.
+    ///
+    /// Drivers with a preview mode (`WIA_IPS_PREVIEW`) pick their own fast
+    /// settings and skip the calibration of a final scan; others scan at a
+    /// low resolution instead. The scan item's preview, resolution, color
+    /// and format settings are put back afterwards, so the next scan is
+    /// configured as it was before.
+    pub fn preview(&self, source: ScanSource) -> Result<Vec<u8>> {
+        let item_props: IWiaPropertyStorage = self.scan_item()?.cast()?;
+
+        // The intent comes first since restoring it resets the others, and
+        // the medium before the format for the same reason
+        let saved: Vec<(u32, WiaValue)> = [
+            WIA_IPS_CUR_INTENT,
+            WIA_IPS_PREVIEW,
+            WIA_IPA_DATATYPE,
+            WIA_IPA_DEPTH,
+            WIA_IPS_XRES,
+            WIA_IPS_YRES,
+            WIA_IPA_TYMED,
+            WIA_IPA_FORMAT,
+        ]
+        .into_iter()
+        .filter_map(|prop_id| Some((prop_id, read_property(&item_props, prop_id).ok()?)))
+        .collect();
+
+        let options = ScanOptions::new()
+            .source(source)
+            .preview(true)
+            .color_mode(ColorMode::Color)
+            .format(ScanFormat::Png);
+        let preview = self.transfer_to_memory(&options);
+
+        for (prop_id, value) in saved {
+            let restored = match value {
+                WiaValue::I4(value) => write_i4_property(&item_props, prop_id, value),
+                WiaValue::Guid(value) => write_guid_property(&item_props, prop_id, value),
+                _ => continue,
+            };
+            if let Err(e) = restored {
+                log::warn!("Failed to restore property {} after the preview: {:?}", prop_id, e);
+            }
+        }
+
+        preview
+    }
+
+    pub(crate) fn transfer_to_memory(&self, options: &ScanOptions) -> Result<Vec<u8>> {
+        BackendDevice::transfer(self, options)
+    }
+
+    /// Transfers the scan item, as configured by [`prepare_banded`], into
+    /// memory through a banded callback.
+    pub(crate) fn transfer_banded(&self) -> Result<Vec<u8>> {
+        let scan_item = self.scan_item()?;
+        let item_props: IWiaPropertyStorage = scan_item.cast()?;
+
+        let data = banded_transfer(&scan_item, &item_props)?;
+        log::info!("Scan complete! Received {} bytes", data.len());
+        Ok(data)
+    }
+
+    /// Scans every page in the document feeder into `output_dir`, one
+    /// numbered file per page (`page_001.png`, `page_002.png`, ...).
+    ///
+    /// Stops once the driver reports the feeder is empty. The written files
+    /// are in [`ScanResult::paths`], in page order. An empty feeder before
+    /// the first page is reported as [`WiaError::PaperEmpty`].
+    pub fn scan_all_pages(
+        &self,
+        output_dir: impl AsRef<Path>,
+        format: ScanFormat,
+    ) -> Result<ScanResult> {
+        let options = ScanOptions::new().source(ScanSource::Feeder).format(format);
+        self.scan_pages(output_dir, &options)
+    }
+
+    /// Like [`scan_all_pages`](Self::scan_all_pages), but scans both sides
+    /// of each sheet. Pages come back in the order the driver delivers
+    /// them, which is front then back for each sheet.
+    pub fn scan_all_pages_duplex(
+        &self,
+        output_dir: impl AsRef<Path>,
+        format: ScanFormat,
+    ) -> Result<ScanResult> {
+        let options = ScanOptions::new().source(ScanSource::FeederDuplex).format(format);
+        self.scan_pages(output_dir, &options)
+    }
+
+    /// Scans pages from the feeder with `options` into `output_dir`, one
+    /// numbered file per page. The written files are in
+    /// [`ScanResult::paths`], in page order, and blank pages left out by
+    /// [`ScanOptions::skip_blank_pages`] are counted in
+    /// [`ScanResult::blank_pages_skipped`].
+    ///
+    /// Takes every page unless [`ScanOptions::pages`] sets a limit; running
+    /// out of paper before the limit ends the scan without an error. The
+    /// source defaults to the feeder and the format to BMP, and the
+    /// option's output path is ignored.
+    pub fn scan_pages(
+        &self,
+        output_dir: impl AsRef<Path>,
+        options: &ScanOptions,
+    ) -> Result<ScanResult> {
+        options.validate()?;
+        let output_dir = std::path::absolute(output_dir.as_ref())
+            .map_err(|e| WiaError::InvalidOption(format!("Invalid output path: {}", e)))?;
+        if !output_dir.is_dir() {
+            return Err(WiaError::InvalidOption(format!(
+                "Output directory does not exist: {}",
+                output_dir.display()
+            )));
+        }
+
+        let mut options = options.clone();
+        options.source.get_or_insert(ScanSource::Feeder);
+        options.pages.get_or_insert(ALL_PAGES);
+        let format = *options.format.get_or_insert(ScanFormat::Bmp);
+
+        let fed = feed_pages(self, &options, |page| {
+            output_dir.join(format!("page_{:03}.{}", page, format.extension()))
+        })?;
+
+        log::info!("Scanned {} page(s) into {}", fed.paths.len(), output_dir.display());
+        if fed.multi_feed {
+            return Err(WiaError::MultiFeedAfter {
+                pages: fed.paths.len() as u32,
+            });
+        }
+
+        let item_props = self.item_properties(ItemLevel::Scan)?;
+        let pages = fed.paths.len() as u32;
+        Ok(fed.configured.apply_to(ScanResult {
+            pages,
+            blank_pages_skipped: fed.blank_skipped,
+            regions: 1,
+            ..scan_result(fed.paths, &item_props)
+        }))
+    }
+
+    /// Scans every page in the document feeder into a single multi-page
+    /// TIFF or PDF at `output`.
+    ///
+    /// Pages are transferred as bitmaps into a temporary directory and
+    /// stored uncompressed, so expect large files at high resolutions. The
+    /// PDF holds page images only, without a text layer.
+    ///
+    /// A double feed caught by multi-feed detection still writes the pages
+    /// before it to `output`, then fails with [`WiaError::MultiFeedAfter`].
+    pub fn scan_to_document(
+        &self,
+        output: impl AsRef<Path>,
+        format: DocumentFormat,
+    ) -> Result<ScanResult> {
+        self.scan_to_document_with_options(output, format, &ScanOptions::new())
+    }
+
+    /// Like [`scan_to_document`](Self::scan_to_document), with the pages
+    /// scanned using `options`. The source defaults to the feeder, and the
+    /// format and output path of `options` are ignored.
+    pub fn scan_to_document_with_options(
+        &self,
+        output: impl AsRef<Path>,
+        format: DocumentFormat,
+        options: &ScanOptions,
+    ) -> Result<ScanResult> {
+        options.validate()?;
+        let output = std::path::absolute(output.as_ref())
+            .map_err(|e| WiaError::InvalidOption(format!("Invalid output path: {}", e)))?;
+        if let Some(parent) = output.parent()
+            && !parent.is_dir()
+        {
+            return Err(WiaError::InvalidOption(format!(
+                "Output directory does not exist: {}",
+                parent.display()
+            )));
+        }
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let temp_dir =
+            std::env::temp_dir().join(format!("wia-rs-{}-{}", std::process::id(), nanos));
+        std::fs::create_dir(&temp_dir).map_err(|e| WiaError::Io(e.to_string()))?;
+
+        let mut options = options.clone().format(ScanFormat::Bmp);
+        options.source.get_or_insert(ScanSource::Feeder);
+        options.pages.get_or_insert(ALL_PAGES);
+        let fed = feed_pages(self, &options, |page| temp_dir.join(format!("page_{:03}.bmp", page)))
+            .and_then(|fed| {
+                let pages = fed
+                    .paths
+                    .iter()
+                    .map(|path| {
+                        let bytes = std::fs::read(path).map_err(|e| WiaError::Io(e.to_string()))?;
+                        document::decode_bmp(&bytes)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((pages, fed.blank_skipped, fed.multi_feed, fed.configured))
+            });
+        // The bitmaps are only needed until they're decoded
+        if let Err(e) = std::fs::remove_dir_all(&temp_dir) {
+            log::warn!("Failed to remove {}: {}", temp_dir.display(), e);
+        }
+        let (pages, blank_skipped, multi_feed, configured) = fed?;
+        if multi_feed && pages.is_empty() {
+            return Err(WiaError::MultiFeedAfter { pages: 0 });
+        }
+
+        log::debug!("Writing {} page(s) to {}", pages.len(), output.display());
+        let format = match format {
+            DocumentFormat::Tiff => {
+                document::write_tiff(&pages, &output)?;
+                WiaImgFmt_TIFF
+            }
+            // WIA has no GUID for plain PDF
+            DocumentFormat::Pdf => {
+                document::write_pdf(&pages, &output)?;
+                GUID::zeroed()
+            }
+        };
+
+        if multi_feed {
+            return Err(WiaError::MultiFeedAfter {
+                pages: pages.len() as u32,
+            });
+        }
+
+        let bytes = std::fs::metadata(&output)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        Ok(configured.apply_to(ScanResult {
+            path: output.clone(),
+            bytes,
+            pages: pages.len() as u32,
+            blank_pages_skipped: blank_skipped,
+            regions: 1,
+            paths: vec![output],
+            format,
+            color_mode: None,
+            bit_depth: None,
+            warnings: Vec::new(),
+            rotated_by: None,
+            duplex: false,
+            attempt: 1,
+            effective: EffectiveSettings::default(),
+        }))
+    }
+
+    /// Checks `options` against the values the driver reports it accepts,
+    /// without scanning or changing anything, so a saved profile can be
+    /// vetted for this device. Every incompatible setting is listed in
+    /// [`WiaError::InvalidSettings`].
+    ///
+    /// The same check runs before each scan unless [`ScanOptions::force`]
+    /// is set; this one runs regardless.
+    pub fn check_options(&self, options: &ScanOptions) -> Result<()> {
+        options.validate()?;
+        let scan_item = match &options.item {
+            Some(name) => self.find_item(name)?,
+            None => self.scan_item()?,
+        };
+        validate_settings(&scan_item, options)
+    }
+
+    /// Applies the source and other settings from `options` and returns the
+    /// item to transfer from along with its properties and what configuring
+    /// it reported.
+    fn prepare_item(
+        &self,
+        options: &ScanOptions,
+    ) -> Result<(IWiaItem, IWiaPropertyStorage, Configured)> {
+        let configured = prepare(self, options)?;
+        let scan_item = self.scan_item()?;
+        let item_props: IWiaPropertyStorage = scan_item.cast()?;
+        Ok((scan_item, item_props, configured))
+    }
+
+    /// The item scans are transferred from. It is looked up once and reused
+    /// until [`refresh`](Self::refresh) is called.
+    pub(crate) fn scan_item(&self) -> Result<IWiaItem> {
+        if let Some(item) = self.scan_item.borrow().as_ref() {
+            return Ok(item.clone());
+        }
+
+        let item = self.first_child_item()?.ok_or(WiaError::NoScannerItem)?;
+        self.scan_item.replace(Some(item.clone()));
+        Ok(item)
+    }
+
+    /// The property storage of the root or scan item.
+    pub(crate) fn item_properties(&self, level: ItemLevel) -> Result<IWiaPropertyStorage> {
+        match level {
+            ItemLevel::Root => Ok(self.props.clone()),
+            ItemLevel::Scan => Ok(self.scan_item()?.cast()?),
+        }
+    }
+}
+
+/// Applies the source and other settings from `options` to a device's scan
+/// item, checking them first unless [`ScanOptions::force`] is set, and
+/// returns what configuring it reported.
+pub(crate) fn prepare<D: BackendDevice + ?Sized>(
+    device: &D,
+    options: &ScanOptions,
+) -> Result<Configured> {
+    prepare_with_medium(device, options, None)
+}
+
+/// Like [`prepare`], but first switches the scan item to `TYMED_CALLBACK`
+/// for a banded transfer. The medium it replaced is kept in
+/// [`Configured::previous_tymed`] for [`restore_tymed`].
+pub(crate) fn prepare_banded<D: BackendDevice + ?Sized>(
+    device: &D,
+    options: &ScanOptions,
+) -> Result<Configured> {
+    prepare_with_medium(device, options, Some(TYMED_CALLBACK))
+}
+
+fn prepare_with_medium<D: BackendDevice + ?Sized>(
+    device: &D,
+    options: &ScanOptions,
+    tymed: Option<u32>,
+) -> Result<Configured> {
+    // The source is written to the scan item, so it has to be picked first
+    if let Some(name) = &options.item {
+        device.select_item(name)?;
+    }
+    let scan_item = device.item(ItemLevel::Scan);
+    if !options.force {
+        validate_settings(&scan_item, options)?;
+    }
+
+    let source = select_source(device, options)?;
+
+    // Drivers reset the format when the medium changes, so the medium has
+    // to be written before configure_item writes the format
+    let mut previous_tymed = None;
+    if let Some(tymed) = tymed {
+        previous_tymed = scan_item.read_i4(WIA_IPA_TYMED).ok();
+        scan_item.write_i4(WIA_IPA_TYMED, tymed as i32)?;
+    }
+
+    let mut configured = match configure_item(&scan_item, options) {
+        Ok(configured) => configured,
+        Err(e) => {
+            restore_tymed(device, previous_tymed);
+            return Err(e);
+        }
+    };
+    configured.duplex = source == Some(ScanSource::FeederDuplex);
+    configured.previous_tymed = previous_tymed;
+    Ok(configured)
+}
+
+/// Puts back the medium [`prepare_banded`] replaced, so later file
+/// transfers aren't left in callback mode.
+pub(crate) fn restore_tymed<D: BackendDevice + ?Sized>(device: &D, previous: Option<i32>) {
+    if let Some(tymed) = previous
+        && let Err(e) = device.write_property(ItemLevel::Scan, WIA_IPA_TYMED, tymed)
+    {
+        log::warn!("Failed to restore the media type after the transfer: {:?}", e);
+    }
+}
+
+/// Combines the document handling a device's root and scan item report.
+pub(crate) fn capabilities<D: BackendDevice + ?Sized>(device: &D) -> Result<Capabilities> {
+    // First check device level properties for capability detection
+    log::debug!("Checking device level properties...");
+    let root = check_scanner_capabilities(&device.item(ItemLevel::Root))?;
+
+    // Then check item level properties
+    log::debug!("Checking item level properties...");
+    let item = check_scanner_capabilities(&device.item(ItemLevel::Scan))?;
+
+    // Combine results - if either level reports capability, consider it
+    // available, but don't let one level's silence mask the other's answer
+    let capabilities = root.merge(item);
+
+    log::debug!("Final capability detection: {:?}", capabilities);
+
+    Ok(capabilities)
+}
+
+/// Writes the page count and the document handling select for the source
+/// in `options`, where set, and returns the source it resolved to.
+///
+/// Both values go on the root item, where WIA 1.0 drivers read them, and on
+/// the scan item for drivers that follow the WIA 2.0 layout.
+pub(crate) fn select_source<D: BackendDevice + ?Sized>(
+    device: &D,
+    options: &ScanOptions,
+) -> Result<Option<ScanSource>> {
+    if let Some(pages) = options.pages {
+        let pages = pages.min(i32::MAX as u32) as i32;
+        log::debug!("Setting page count to: {}", pages);
+        if let Err(e) = write_root_and_item(device, WIA_DPS_PAGES, WIA_IPS_PAGES, pages) {
+            // Drivers that don't support it transfer a page per call anyway
+            log::warn!("Failed to set page count: {:?}", e);
+        }
+    }
+
+    let Some(source) = options.source else {
+        return Ok(None);
+    };
+
+    let source = resolve_source(device, source);
+    if source == ScanSource::FeederDuplex {
+        check_duplex(device)?;
+    }
+    if source == ScanSource::Transparency {
+        check_transparency(device)?;
+    }
+    let handling_value = source.handling_select().unwrap_or(FLATBED) as i32;
+    log::debug!(
+        "Setting document handling select to: {:?} ({})",
+        source, handling_value
+    );
+
+    if let Err(e) = write_root_and_item(
+        device,
+        WIA_DPS_DOCUMENT_HANDLING_SELECT,
+        WIA_IPS_DOCUMENT_HANDLING_SELECT,
+        handling_value,
+    ) {
+        log::warn!("Failed to set document handling mode: {:?}", e);
+        // Try to continue anyway
+    }
+    select_transparency(device, source == ScanSource::Transparency)?;
+
+    Ok(Some(source))
+}
+
+/// Switches the transparency adapter's light source on for
+/// [`ScanSource::Transparency`] and off for every other source, so a
+/// flatbed scan after a film scan doesn't come out backlit.
+fn select_transparency<D: BackendDevice + ?Sized>(device: &D, selected: bool) -> Result<()> {
+    let value = if selected { LIGHT_SOURCE_SELECT as i32 } else { 0 };
+    match device.write_property(ItemLevel::Root, WIA_DPS_TRANSPARENCY_SELECT, value) {
+        Ok(()) => Ok(()),
+        Err(e) if selected => {
+            log::warn!("Failed to select the transparency adapter: {:?}", e);
+            Err(WiaError::FeatureUnsupported("Transparency adapter".to_string()))
+        }
+        // Most devices have no adapter to switch off
+        Err(e) => {
+            log::trace!("Not deselecting the transparency adapter: {:?}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Writes `value` to `root_id` on the root item and `item_id` on the scan
+/// item, failing only if neither write succeeds.
+fn write_root_and_item<D: BackendDevice + ?Sized>(
+    device: &D,
+    root_id: u32,
+    item_id: u32,
+    value: i32,
+) -> Result<()> {
+    let root = device.write_property(ItemLevel::Root, root_id, value);
+    let item = device.write_property(ItemLevel::Scan, item_id, value);
+    root.or(item)
+}
+
+/// Fails if the device reports that it can't scan both sides. Drivers that
+/// don't report their capabilities get the benefit of the doubt.
+fn check_duplex<D: BackendDevice + ?Sized>(device: &D) -> Result<()> {
+    if capabilities(device)?.duplex == Some(false) {
+        return Err(WiaError::InvalidOption(
+            "Device doesn't support duplex scanning".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails if the device reports that it has no transparency adapter, giving
+/// drivers that don't say the same benefit of the doubt.
+fn check_transparency<D: BackendDevice + ?Sized>(device: &D) -> Result<()> {
+    if capabilities(device)?.transparency == Some(false) {
+        return Err(WiaError::FeatureUnsupported(
+            "Transparency adapter".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Picks the feeder for [`ScanSource::Auto`] when it reports paper loaded,
+/// and the flatbed otherwise. Other sources are returned as is.
+fn resolve_source<D: BackendDevice + ?Sized>(device: &D, source: ScanSource) -> ScanSource {
+    if source != ScanSource::Auto {
+        return source;
+    }
+
+    match device.feeder_status() {
+        Ok(status) if status.paper_loaded => {
+            log::debug!("Paper detected in the feeder");
+            ScanSource::Feeder
+        }
+        _ => ScanSource::Flatbed,
+    }
+}
+
+/// Transfers pages until the feeder runs out or the page count in `options`
+/// is reached, saving page `n` (counting from 1) to `page_path(n)`.
+fn feed_pages<D: BackendDevice + ?Sized>(
+    device: &D,
+    options: &ScanOptions,
+    page_path: impl Fn(usize) -> PathBuf,
+) -> Result<FedPages> {
+    let configured = prepare(device, options)?;
+    let limit = options.pages.filter(|pages| *pages != ALL_PAGES);
+
+    let mut pages = Vec::new();
+    let mut blank_skipped = 0;
+    let mut multi_feed = false;
+    loop {
+        let path = page_path(pages.len() + 1);
+
+        log::debug!("Saving page {} to {}", pages.len() + 1, path.display());
+        match device.transfer_file(&path) {
+            // S_FALSE means the driver had nothing left to transfer
+            Ok(hr) if hr == S_FALSE => break,
+            Ok(hr) => {
+                if configured.detect_blank && is_blank_page(&path) {
+                    log::debug!("Skipping blank page {}", path.display());
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        log::warn!("Failed to remove {}: {}", path.display(), e);
+                    }
+                    blank_skipped += 1;
+                } else {
+                    if configured.bmp_to_pdf {
+                        wrap_in_pdf(&path)?;
+                    }
+                    pages.push(path);
+                }
+                if limit == Some(pages.len() as u32 + blank_skipped) {
+                    break;
+                }
+                match WiaStatus::from_hresult(hr) {
+                    Some(WiaStatus::EndOfMedia) => break,
+                    Some(status) => log::debug!("Transfer reported {}", status),
+                    None => {}
+                }
+            }
+            Err(WiaError::PaperEmpty) if !pages.is_empty() || blank_skipped > 0 => break,
+            // The pages so far are fine, so hand them back with the error
+            Err(WiaError::MultiFeed) => {
+                log::warn!("Double feed detected after {} page(s)", pages.len());
+                multi_feed = true;
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+
+        // Drivers that keep returning S_OK on an empty feeder would
+        // otherwise loop forever, so trust the feed status when present
+        if let Ok(status) = device.feeder_status()
+            && !status.paper_loaded
+        {
+            break;
+        }
+    }
+
+    Ok(FedPages {
+        paths: pages,
+        blank_skipped,
+        multi_feed,
+        configured,
+    })
+}
+
+/// What [`feed_pages`] transferred.
+struct FedPages {
+    paths: Vec<PathBuf>,
+    /// Pages left out because [`is_blank_page`] found them blank.
+    blank_skipped: u32,
+    /// Whether the feed stopped at a double feed rather than running out.
+    multi_feed: bool,
+    configured: Configured,
+}
+
+/// Whether a transferred page is blank. Pages that aren't bitmaps can't be
+/// checked and are kept.
+fn is_blank_page(path: &Path) -> bool {
+    let page = std::fs::read(path)
+        .map_err(|e| WiaError::Io(e.to_string()))
+        .and_then(|bytes| document::decode_bmp(&bytes));
+    match page {
+        Ok(page) => page.is_blank(),
+        Err(e) => {
+            log::debug!("Can't check {} for a blank page: {:?}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// What [`configure_item`] did beyond what was asked, to be reported in
+/// the [`ScanResult`].
+#[derive(Default)]
+pub(crate) struct Configured {
+    warnings: Vec<String>,
+    rotated_by: Option<RotationProperty>,
+    /// Set by the caller, since the source is selected separately.
+    pub(crate) duplex: bool,
+    /// Blank pages were asked to be skipped but the driver can't, so the
+    /// transferred pages have to be checked instead.
+    detect_blank: bool,
+    /// PDF was asked for but the driver can't produce it, so BMPs are
+    /// transferred and have to be wrapped, see [`wrap_in_pdf`].
+    pub(crate) bmp_to_pdf: bool,
+    /// The medium [`prepare_banded`] switched away from, if it could read
+    /// it.
+    pub(crate) previous_tymed: Option<i32>,
+    effective: EffectiveSettings,
+}
+
+impl Configured {
+    pub(crate) fn apply_to(self, result: ScanResult) -> ScanResult {
+        ScanResult {
+            warnings: self.warnings,
+            rotated_by: self.rotated_by,
+            duplex: self.duplex,
+            // WIA has no GUID for the plain PDF the crate writes
+            format: if self.bmp_to_pdf { GUID::zeroed() } else { result.format },
+            effective: self.effective,
+            ..result
+        }
+    }
+}
+
+/// Applies the settings in `options` to a scan item. Anything unset is left
+/// alone.
+pub(crate) fn configure_item(
+    scan_item: &impl ItemProperties,
+    options: &ScanOptions,
+) -> Result<Configured> {
+    let mut warnings = Vec::new();
+    let mut rotated_by = None;
+
+    // Before the resolution, since changing the intent can reset it
+    let intent = options.intent_flags();
+    if let Some(color_mode) = options.color_mode {
+        log::debug!("Setting color mode to {:?} ({:?})", color_mode, intent);
+        set_color_mode_with_intent(scan_item, color_mode, intent)?;
+    } else if !intent.is_empty() {
+        log::debug!("Setting intent to {:?}", intent);
+        set_intent(scan_item, intent)?;
+    }
+
+    if let Some(depth) = options.bit_depth {
+        log::debug!("Setting bit depth to {}", depth);
+        set_bit_depth(scan_item, depth)?;
+    }
+
+    if let Some(threshold) = options.threshold {
+        // Fall back to what the driver has when the mode isn't being changed
+        let color_mode = options.color_mode.or_else(|| {
+            scan_item.read_i4(WIA_IPA_DATATYPE)
+                .ok()
+                .and_then(ColorMode::from_data_type)
+        });
+        if color_mode == Some(ColorMode::BlackAndWhite) {
+            log::debug!("Setting threshold to {}", threshold);
+            set_threshold(scan_item, threshold)?;
+        } else {
+            warnings.push(format!(
+                "Threshold {} was not applied, it only affects black-and-white scans",
+                threshold
+            ));
+        }
+    }
+
+    let (mut x_dpi, mut y_dpi) = (options.x_dpi, options.y_dpi);
+    if let Some(preview) = options.preview {
+        log::debug!("Setting preview mode to {}", preview);
+        match set_preview(scan_item, preview) {
+            Ok(()) => {}
+            // A final scan is what drivers without the property do anyway
+            Err(WiaError::FeatureUnsupported(_)) if !preview => {}
+            Err(WiaError::FeatureUnsupported(_)) => {
+                if x_dpi.is_none() && y_dpi.is_none() {
+                    (x_dpi, y_dpi) = (Some(PREVIEW_DPI), Some(PREVIEW_DPI));
+                }
+                warnings.push(format!(
+                    "The device has no preview mode, scanning at {} DPI instead",
+                    x_dpi.or(y_dpi).unwrap_or(PREVIEW_DPI)
+                ));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    match (x_dpi, y_dpi) {
+        (Some(x_dpi), Some(y_dpi)) if x_dpi == y_dpi => {
+            log::debug!("Setting resolution to {} DPI", x_dpi);
+            set_resolution(scan_item, x_dpi)?;
+        }
+        (None, None) => {}
+        (x_dpi, y_dpi) => {
+            // An axis left unset keeps whatever the driver has now
+            let current = |prop_id| scan_item.read_i4(prop_id).map(|dpi| dpi as u32);
+            let x_dpi = x_dpi.map_or_else(|| current(WIA_IPS_XRES), Ok)?;
+            let y_dpi = y_dpi.map_or_else(|| current(WIA_IPS_YRES), Ok)?;
+            log::debug!("Setting resolution to {}x{} DPI", x_dpi, y_dpi);
+            set_resolution_xy(scan_item, x_dpi, y_dpi)?;
+        }
+    }
+
+    if let Some(page_size) = options.page_size {
+        log::debug!("Setting page size to {:?}", page_size);
+        match set_page_size(scan_item, page_size) {
+            Ok(()) => {}
+            Err(e) if page_size == PageSize::Auto => warnings.push(format!(
+                "Automatic page size detection isn't available, keeping the current size: {}",
+                e
+            )),
+            Err(e) => return Err(e),
+        }
+    }
+
+    // After the resolution, since the region is measured in its pixels
+    if let Some(region) = options.region {
+        log::debug!("Setting scan region to {:?}", region);
+        set_scan_region(scan_item, region.x, region.y, region.width, region.height)?;
+    }
+
+    if let Some(rotation) = options.rotation {
+        log::debug!("Setting rotation to {:?}", rotation);
+        rotated_by = Some(set_rotation(scan_item, rotation)?);
+    }
+
+    if let Some(orientation) = options.orientation {
+        log::debug!("Setting orientation to {:?}", orientation);
+        rotated_by = Some(set_orientation(scan_item, orientation)?);
+    }
+
+    if let Some(deskew) = options.deskew {
+        log::debug!("Setting automatic deskew to {}", deskew);
+        set_deskew(scan_item, deskew)?;
+    }
+
+    if let Some(auto_crop) = options.auto_crop {
+        log::debug!("Setting automatic cropping to {:?}", auto_crop);
+        set_auto_crop(scan_item, auto_crop)?;
+    }
+
+    if let Some(detect) = options.multi_feed_detection {
+        log::debug!("Setting multi-feed detection to {}", detect);
+        set_multi_feed_detection(scan_item, detect, options.multi_feed_sensitivity)?;
+    }
+
+    let mut detect_blank = false;
+    if let Some(skip) = options.skip_blank_pages {
+        log::debug!("Setting blank page skipping to {}", skip);
+        match set_blank_page_skipping(scan_item, skip, options.blank_page_sensitivity) {
+            Ok(()) => {}
+            Err(WiaError::FeatureUnsupported(_)) => {
+                log::debug!("Driver can't skip blank pages, checking them after the transfer");
+                detect_blank = skip;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if let Some(auto_exposure) = options.auto_exposure {
+        log::debug!("Setting automatic exposure to {}", auto_exposure);
+        match set_auto_exposure(scan_item, auto_exposure) {
+            // Without the property the exposure is manual already
+            Err(WiaError::FeatureUnsupported(_)) if !auto_exposure => {}
+            result => result?,
+        }
+    }
+
+    if let Some(level) = options.brightness {
+        let written = match level {
+            Level::Absolute(value) => set_brightness(scan_item, value)?,
+            Level::Normalized(value) => set_brightness_normalized(scan_item, value)?,
+        };
+        log::debug!("Set brightness to {}", written);
+        warnings.extend(level.clamp_warning("Brightness", written));
+    }
+
+    if let Some(level) = options.contrast {
+        let written = match level {
+            Level::Absolute(value) => set_contrast(scan_item, value)?,
+            Level::Normalized(value) => set_contrast_normalized(scan_item, value)?,
+        };
+        log::debug!("Set contrast to {}", written);
+        warnings.extend(level.clamp_warning("Contrast", written));
+    }
+
+    let mut bmp_to_pdf = false;
+    if let Some(format) = options.format {
+        log::debug!("Setting output format to {:?}", format);
+        match write_format(scan_item, format) {
+            Err(e) if format == ScanFormat::Pdf && cfg!(feature = "pdf") => {
+                log::debug!("The driver can't produce PDF ({:?}), scanning a BMP", e);
+                write_format(scan_item, ScanFormat::Bmp)?;
+                bmp_to_pdf = true;
+            }
+            Err(_) if format == ScanFormat::Pdf => {
+                return Err(WiaError::FeatureUnsupported("PDF output".to_string()));
+            }
+            result => result?,
+        }
+    }
+
+    // The pages are decoded as plain bitmaps to be wrapped, which
+    // compressed BMP data can't be
+    if bmp_to_pdf && (options.compression.is_some() || options.jpeg_quality.is_some()) {
+        warnings.push(
+            "Compression is not applied to PDF pages the driver scans as BMP".to_string(),
+        );
+    } else if let Some(compression) = options.compression {
+        log::debug!("Setting compression to {:?}", compression);
+        set_compression(scan_item, compression)?;
+    }
+
+    // The quality itself went into the intent above
+    if options.jpeg_quality.is_some() && options.compression.is_none() && !bmp_to_pdf {
+        log::debug!("Setting JPEG compression");
+        // Compression is optional for drivers, so carry on without it
+        if let Err(e) = scan_item.write_i4(WIA_IPA_COMPRESSION, WIA_COMPRESSION_JPEG as i32) {
+            log::warn!("Failed to select JPEG compression: {:?}", e);
+        }
+    }
+
+    let effective = read_effective_settings(scan_item);
+    if options.strict {
+        check_effective(options, (x_dpi, y_dpi), &effective)?;
+    }
+
+    for warning in &warnings {
+        log::warn!("{}", warning);
+    }
+    Ok(Configured {
+        warnings,
+        rotated_by,
+        duplex: false,
+        detect_blank,
+        bmp_to_pdf,
+        previous_tymed: None,
+        effective,
+    })
+}
+
+/// Writes the output format and checks the driver kept it, since some
+/// substitute a format of their own instead of rejecting the write.
+fn write_format(scan_item: &impl ItemProperties, format: ScanFormat) -> Result<()> {
+    scan_item.write_guid(WIA_IPA_FORMAT, format.guid())?;
+    match scan_item.read_property(WIA_IPA_FORMAT) {
+        Ok(WiaValue::Guid(written)) if written != format.guid() => {
+            log::debug!("Asked for {:?} output, the driver chose {:?}", format, written);
+            Err(WiaError::FeatureUnsupported(format!("{:?} output", format)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads back the settings a driver may clamp or ignore.
+fn read_effective_settings(scan_item: &impl ItemProperties) -> EffectiveSettings {
+    let i4 = |prop_id| scan_item.read_i4(prop_id).ok();
+    EffectiveSettings {
+        x_dpi: i4(WIA_IPS_XRES).and_then(|dpi| u32::try_from(dpi).ok()),
+        y_dpi: i4(WIA_IPS_YRES).and_then(|dpi| u32::try_from(dpi).ok()),
+        color_mode: i4(WIA_IPA_DATATYPE).and_then(ColorMode::from_data_type),
+        bit_depth: i4(WIA_IPA_DEPTH).and_then(|depth| u8::try_from(depth).ok()),
+        threshold: i4(WIA_IPS_THRESHOLD).and_then(|threshold| u8::try_from(threshold).ok()),
+        brightness: i4(WIA_IPS_BRIGHTNESS),
+        contrast: i4(WIA_IPS_CONTRAST),
+    }
+}
+
+/// Fails with every setting the driver reports using a different value
+/// for than `options` asked, for [`ScanOptions::strict`]. The resolution
+/// is the one that was written, which a preview fallback may have set.
+fn check_effective(
+    options: &ScanOptions,
+    (x_dpi, y_dpi): (Option<u32>, Option<u32>),
+    effective: &EffectiveSettings,
+) -> Result<()> {
+    // Normalized levels are scaled to the device's range, so only
+    // absolute ones can be compared
+    let absolute = |level| match level {
+        Some(Level::Absolute(value)) => Some(value),
+        _ => None,
+    };
+
+    let mismatches: Vec<SettingError> = [
+        mismatch("Horizontal resolution", x_dpi, effective.x_dpi),
+        mismatch("Vertical resolution", y_dpi, effective.y_dpi),
+        mismatch("Color mode", options.color_mode, effective.color_mode),
+        mismatch("Bit depth", options.bit_depth, effective.bit_depth),
+        mismatch("Threshold", options.threshold, effective.threshold),
+        mismatch("Brightness", absolute(options.brightness), effective.brightness),
+        mismatch("Contrast", absolute(options.contrast), effective.contrast),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(WiaError::InvalidSettings(mismatches))
+    }
+}
+
+fn mismatch<T: PartialEq + std::fmt::Debug>(
+    setting: &str,
+    requested: Option<T>,
+    effective: Option<T>,
+) -> Option<SettingError> {
+    let (requested, effective) = (requested?, effective?);
+    (requested != effective).then(|| SettingError {
+        setting: setting.to_string(),
+        error: WiaError::InvalidOption(format!(
+            "{} {:?} was asked for, but the driver is using {:?}",
+            setting, requested, effective
+        )),
+    })
+}
+
+/// Checks the values `options` will write against the valid values the
+/// driver reports, before anything is written, so a bad setting can't
+/// leave the item half configured. Every problem is reported at once in
+/// [`WiaError::InvalidSettings`]. Callers skip it for
+/// [`ScanOptions::force`].
+///
+/// Properties the driver reports no valid values for are left to the
+/// write, as are brightness and contrast, which are clamped instead.
+pub(crate) fn validate_settings(
+    scan_item: &impl ItemProperties,
+    options: &ScanOptions,
+) -> Result<()> {
+    let dpi = |dpi: u32| dpi.min(i32::MAX as u32) as i32;
+    let mut checks: Vec<(&str, u32, i32)> = Vec::new();
+    if let Some(color_mode) = options.color_mode {
+        checks.push(("Data type", WIA_IPA_DATATYPE, color_mode.data_type() as i32));
+    }
+    // The valid depths follow the data type, which isn't written yet
+    if let Some(depth) = options.bit_depth
+        && options.color_mode.is_none()
+    {
+        checks.push(("Bit depth", WIA_IPA_DEPTH, depth as i32));
+    }
+    if let Some(threshold) = options.threshold {
+        checks.push(("Threshold", WIA_IPS_THRESHOLD, threshold as i32));
+    }
+    if let Some(x_dpi) = options.x_dpi {
+        checks.push(("Horizontal resolution", WIA_IPS_XRES, dpi(x_dpi)));
+    }
+    if let Some(y_dpi) = options.y_dpi {
+        checks.push(("Vertical resolution", WIA_IPS_YRES, dpi(y_dpi)));
+    }
+    if let Some(deskew) = options.deskew {
+        let value = if deskew { WIA_AUTO_DESKEW_ON } else { WIA_AUTO_DESKEW_OFF };
+        checks.push(("Automatic deskew", WIA_IPS_AUTO_DESKEW, value as i32));
+    }
+    if let Some(auto_crop) = options.auto_crop {
+        checks.push(("Automatic cropping", WIA_IPS_AUTO_CROP, auto_crop.wia_value() as i32));
+    }
+    if let Some(compression) = options.compression {
+        checks.push(("Compression", WIA_IPA_COMPRESSION, compression.wia_value() as i32));
+    }
+    if let Some(auto_exposure) = options.auto_exposure {
+        let value = if auto_exposure { EXPOSUREMODE_AUTO } else { EXPOSUREMODE_MANUAL };
+        checks.push(("Exposure mode", WIA_DPC_EXPOSURE_MODE, value as i32));
+    }
+
+    let errors: Vec<SettingError> = checks
+        .into_iter()
+        .filter_map(|(setting, prop_id, value)| {
+            let error = validate_i4(scan_item, prop_id, setting, value).err()?;
+            Some(SettingError {
+                setting: setting.to_string(),
+                error,
+            })
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        log::debug!("{} setting(s) failed validation", errors.len());
+        Err(WiaError::InvalidSettings(errors))
+    }
+}
+
+/// Replaces the BMP at `path` with a single-page PDF of the same image.
+pub(crate) fn wrap_in_pdf(path: &Path) -> Result<()> {
+    log::debug!("Wrapping {} into a PDF", path.display());
+    let bytes = std::fs::read(path).map_err(|e| WiaError::Io(e.to_string()))?;
+    let page = document::decode_bmp(&bytes)?;
+    document::write_pdf(&[page], path)
+}
+
+/// Resolves where `options` wants the scan written, as an absolute path so
+/// the caller gets back exactly the file the driver wrote, whatever the
+/// working directory.
+pub(crate) fn absolute_output_path(options: &ScanOptions) -> Result<PathBuf> {
+    std::path::absolute(options.output_path())
+        .map_err(|e| WiaError::InvalidOption(format!("Invalid output path: {}", e)))
+}
+
+/// Transfers a single-page scan to `output_path` and returns the files
+/// written.
+///
+/// With [`AutoCrop::MultiRegion`], drivers expose each detected region as a
+/// child of the scan item. Those are transferred one by one instead, the
+/// first to `output_path` and the rest next to it with a number appended.
+pub(crate) fn transfer_scan(
+    scan_item: &IWiaItem,
+    options: &ScanOptions,
+    output_path: &Path,
+    callback: Option<&IWiaDataCallback>,
+) -> Result<Vec<PathBuf>> {
+    let regions = if options.auto_crop == Some(AutoCrop::MultiRegion) {
+        // A driver that found a single region may not create children at all
+        child_items(scan_item).unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    if regions.is_empty() {
+        log::debug!("Saving document to {}", output_path.display());
+        // A single-page scan has no use for the success code
+        let data_transfer: IWiaDataTransfer = scan_item.cast()?;
+        let _ = transfer_to_file(&data_transfer, output_path, callback)?;
+        return Ok(vec![output_path.to_path_buf()]);
+    }
+
+    log::debug!("Transferring {} detected regions", regions.len());
+    let mut paths = Vec::with_capacity(regions.len());
+    for (index, region) in regions.iter().enumerate() {
+        let path = region_path(output_path, index + 1);
+        log::debug!("Saving region {} to {}", index + 1, path.display());
+        let data_transfer: IWiaDataTransfer = region.cast()?;
+        let _ = transfer_to_file(&data_transfer, &path, callback)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// `output_path` for the first region, and `name_002.ext` style siblings of
+/// it for the ones after.
+fn region_path(output_path: &Path, region: usize) -> PathBuf {
+    if region == 1 {
+        return output_path.to_path_buf();
+    }
+
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}_{:03}", stem, region);
+    if let Some(extension) = output_path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    output_path.with_file_name(name)
+}
+
+fn child_items(item: &IWiaItem) -> Result<Vec<IWiaItem>> {
+    let mut children = Vec::new();
+    unsafe {
+        let enum_items: IEnumWiaItem = item.EnumChildItems()?;
+        loop {
+            let mut child_item: Option<IWiaItem> = None;
+            let mut num_fetched: u32 = 0;
+            enum_items.Next(1, &mut child_item, &mut num_fetched)?;
+            match child_item {
+                Some(child_item) if num_fetched > 0 => children.push(child_item),
+                _ => break,
+            }
+        }
+    }
+    Ok(children)
+}
+
+/// Reads the vendor, serial number and firmware version from the root
+/// item, each `None` if the driver doesn't expose it.
+fn read_hardware_info(
+    props: &IWiaPropertyStorage,
+) -> (Option<String>, Option<String>, Option<String>) {
+    (
+        read_optional_bstr_property(props, WIA_DIP_VEND_DESC),
+        read_optional_bstr_property(props, WIA_WSD_SERIAL_NUMBER),
+        read_optional_bstr_property(props, WIA_DPA_FIRMWARE_VERSION),
+    )
+}
+
+/// Enumerates the items below `root`, descending into folders, for
+/// devices that nest items more than one level deep, such as a camera's
+/// storage folders.
+fn walk_items<'m>(root: &IWiaItem) -> Result<Vec<WiaItemNode<'m>>> {
+    child_items(root)?
+        .into_iter()
+        .map(|item| {
+            let props: IWiaPropertyStorage = item.cast()?;
+            let flags = read_i4_property(&props, WIA_IPA_ITEM_FLAGS)? as u32;
+            let children = if flags & WiaItemTypeFolder != 0 {
+                walk_items(&item)?
+            } else {
+                Vec::new()
+            };
+            Ok(WiaItemNode {
+                info: item_info(&item)?,
+                flags,
+                children,
+                item,
+                _manager: PhantomData,
+            })
+        })
+        .collect()
+}
+
+/// Transfers an image a device has stored, such as a picture on a camera,
+/// into `output`, in whatever format the device stored it in.
+///
+/// Unlike scanning there's nothing to configure, so this works on any item
+/// whose flags mark it as an image or a file, such as the nodes of
+/// [`WiaDevice::item_tree`].
+/// Other items fail with [`WiaError::InvalidOption`].
+pub fn download_image(item: &IWiaItem, output: impl AsRef<Path>) -> Result<ScanResult> {
+    let props: IWiaPropertyStorage = item.cast()?;
+    let flags = read_i4_property(&props, WIA_IPA_ITEM_FLAGS)? as u32;
+    if flags & (WiaItemTypeImage | WiaItemTypeFile) == 0 {
+        return Err(WiaError::InvalidOption(format!(
+            "Item {} is not a stored image",
+            read_bstr_property(&props, WIA_IPA_ITEM_NAME).unwrap_or_default()
+        )));
+    }
+
+    let output = std::path::absolute(output.as_ref())
+        .map_err(|e| WiaError::InvalidOption(format!("Invalid output path: {}", e)))?;
+    log::debug!("Downloading item to {}", output.display());
+    let data_transfer: IWiaDataTransfer = item.cast()?;
+    let _ = transfer_to_file(&data_transfer, &output, None)?;
+
+    Ok(scan_result(vec![output], &props))
+}
+
+fn item_info(item: &IWiaItem) -> Result<WiaItemInfo> {
+    let props: IWiaPropertyStorage = item.cast()?;
+    Ok(WiaItemInfo {
+        name: read_bstr_property(&props, WIA_IPA_ITEM_NAME)?,
+        full_name: read_optional_bstr_property(&props, WIA_IPA_FULL_ITEM_NAME).unwrap_or_default(),
+    })
+}
+
+/// Describes the files a single-page transfer just wrote, the first of
+/// which is the main output.
+pub(crate) fn scan_result(paths: Vec<PathBuf>, item_props: &IWiaPropertyStorage) -> ScanResult {
+    let output_path = paths.first().cloned().unwrap_or_default();
+    let bytes = std::fs::metadata(&output_path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    // The file is already written, so a failed readback shouldn't fail the scan
+    let format = match read_property(item_props, WIA_IPA_FORMAT) {
+        Ok(WiaValue::Guid(format)) => format,
+        _ => GUID::zeroed(),
+    };
+
+    let color_mode = read_i4_property(item_props, WIA_IPA_DATATYPE)
+        .ok()
+        .and_then(ColorMode::from_data_type);
+    let bit_depth = read_i4_property(item_props, WIA_IPA_DEPTH)
+        .ok()
+        .and_then(|depth| u8::try_from(depth).ok());
+
+    ScanResult {
+        path: output_path,
+        bytes,
+        pages: 1,
+        blank_pages_skipped: 0,
+        regions: paths.len() as u32,
+        paths,
+        format,
+        color_mode,
+        bit_depth,
+        warnings: Vec::new(),
+        rotated_by: None,
+        duplex: false,
+        attempt: 1,
+        effective: EffectiveSettings::default(),
+    }
+}
+
+/// Connects to the device's root item through the device manager.
+fn create_device(device_manager: &IWiaDevMgr, device_id: &DeviceId) -> Result<IWiaItem> {
+    log::debug!("Connecting to device: {}", device_id);
+    let item = unsafe { device_manager.CreateDevice(&device_id.to_bstr()) }.map_err(|e| {
+        // Unknown IDs are reported as either of these, depending on
+        // whether the device was unplugged or its driver removed
+        if e.code() == WIA_S_NO_DEVICE_AVAILABLE || e.code() == E_INVALIDARG {
+            WiaError::DeviceNotFound(device_id.clone())
+        } else {
+            WiaError::from(e)
+        }
+    })?;
+    log::debug!("Connected to device: {}", device_id);
+    Ok(item)
+}
+
+/// Runs a banded transfer of `scan_item`, which has to be in callback mode,
+/// collecting the bands in memory.
+fn banded_transfer(scan_item: &IWiaItem, item_props: &IWiaPropertyStorage) -> Result<Vec<u8>> {
+    // Use the driver's preferred band size, falling back to 64 KiB
+    let buffer_size = read_i4_property(item_props, WIA_IPA_BUFFER_SIZE)
+        .ok()
+        .filter(|size| *size > 0)
+        .unwrap_or(64 * 1024) as u32;
+    let mut transfer_info = WIA_DATA_TRANSFER_INFO {
+        ulSize: std::mem::size_of::<WIA_DATA_TRANSFER_INFO>() as u32,
+        ulBufferSize: buffer_size,
+        bDoubleBuffer: true.into(),
+        ..Default::default()
+    };
+
+    let collector = ComObject::new(MemoryCallback::default());
+    let callback: IWiaDataCallback = collector.to_interface();
+    let data_transfer: IWiaDataTransfer = scan_item.cast()?;
+
+    log::debug!("Transferring document to memory");
+    unsafe {
+        data_transfer.idtGetBandedData(&mut transfer_info, &callback)?;
+    }
+
+    Ok(collector.take_data())
+}
+
+/// Transfers one image through `data_transfer` into the file at `path`, reporting
+/// progress to `callback` if there is one.
+///
+/// Returns the success code of the transfer, since `S_FALSE` and
+/// `WIA_STATUS_END_OF_MEDIA` tell multi-page scans when to stop.
+pub(crate) fn transfer_to_file(
+    data_transfer: &IWiaDataTransfer,
+    path: &Path,
+    callback: Option<&IWiaDataCallback>,
+) -> Result<HRESULT> {
+    let wide_path: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+
+    // Set up the transfer medium
+    let mut stgm = STGMEDIUM {
+        tymed: TYMED_FILE.0 as u32,
+        u: STGMEDIUM_0 {
+            lpszFileName: PWSTR(wide_path.as_ptr() as *mut u16),
+        },
+        ..Default::default()
+    };
+
+    // The generated idtGetData folds every success code into Ok(()), so go
+    // through the vtable to keep S_FALSE and END_OF_MEDIA apart
+    let hr = unsafe {
+        (Interface::vtable(data_transfer).idtGetData)(
+            Interface::as_raw(data_transfer),
+            &mut stgm,
+            callback.map_or(std::ptr::null_mut(), |callback| callback.as_raw()),
+        )
+    };
+    hr.ok()?;
+    Ok(hr)
+}
+
+// Low enough to come back quickly, high enough to recognize the page
+const PREVIEW_DPI: u32 = 75;
+
+/// The document handling a device supports. Each field is `None` when the
+/// driver didn't say, so callers can decide how to treat an unknown source
+/// rather than being handed a guess.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub feeder: Option<bool>,
+    pub flatbed: Option<bool>,
+    /// Whether the feeder can scan both sides of a sheet.
+    pub duplex: Option<bool>,
+    /// Whether the flatbed has a transparency adapter for film, see
+    /// [`ScanSource::Transparency`].
+    pub transparency: Option<bool>,
+}
+
+impl Capabilities {
+    const UNKNOWN: Capabilities = Capabilities {
+        feeder: None,
+        flatbed: None,
+        duplex: None,
+        transparency: None,
+    };
+
+    /// Whether the driver reported anything about its sources.
+    pub fn detected(&self) -> bool {
+        self.feeder.is_some() || self.flatbed.is_some()
+    }
+
+    /// Combines what two items reported. A source counts as available if
+    /// either item has it, and stays unknown only if neither said.
+    fn merge(self, other: Capabilities) -> Capabilities {
+        let either = |a: Option<bool>, b: Option<bool>| match (a, b) {
+            (Some(a), Some(b)) => Some(a || b),
+            (a, b) => a.or(b),
+        };
+        Capabilities {
+            feeder: either(self.feeder, other.feeder),
+            flatbed: either(self.flatbed, other.flatbed),
+            duplex: either(self.duplex, other.duplex),
+            transparency: either(self.transparency, other.transparency),
+        }
+    }
+}
+
+/// The resolutions a device accepts, from
+/// [`WiaDevice::supported_resolutions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolutions {
+    /// Only these resolutions, in the order the driver lists them.
+    List(Vec<u32>),
+    /// Any resolution from `min` to `max` in increments of `step`.
+    Range { min: u32, max: u32, step: u32 },
+}
+
+impl Resolutions {
+    /// Every accepted resolution, with ranges expanded, for filling a
+    /// dropdown.
+    pub fn values(&self) -> Vec<u32> {
+        match *self {
+            Resolutions::List(ref values) => values.clone(),
+            // A step of 0 leaves nothing to step to beyond the minimum
+            Resolutions::Range { min, step: 0, .. } => vec![min],
+            Resolutions::Range { min, max, step } => {
+                (min..=max).step_by(step as usize).collect()
+            }
+        }
+    }
+
+    /// Whether `dpi` is one of the accepted resolutions.
+    pub fn contains(&self, dpi: u32) -> bool {
+        match *self {
+            Resolutions::List(ref values) => values.contains(&dpi),
+            Resolutions::Range { min, max, step } => {
+                (min..=max).contains(&dpi) && (dpi - min).is_multiple_of(step)
+            }
+        }
+    }
+}
+
+/// A child item of a device, as listed by [`WiaDevice::items`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WiaItemInfo {
+    /// The item's name (`WIA_IPA_ITEM_NAME`), which
+    /// [`ScanOptions::item`] selects it by.
+    pub name: String,
+    /// The item's path below the device (`WIA_IPA_FULL_ITEM_NAME`), or
+    /// empty if the driver doesn't report one.
+    pub full_name: String,
+}
+
+/// An item in a device's item tree, from [`WiaDevice::item_tree`].
+///
+/// Like the device it came from, it can't outlive the manager.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WiaItemNode<'m> {
+    pub info: WiaItemInfo,
+    /// The item's `WIA_IPA_ITEM_FLAGS`, a combination of the
+    /// `WiaItemType*` flags.
+    pub flags: u32,
+    /// The items inside a folder, empty for every other item.
+    pub children: Vec<WiaItemNode<'m>>,
+    item: IWiaItem,
+    _manager: PhantomData<&'m WIAScanManager>,
+}
+
+impl WiaItemNode<'_> {
+    /// The item itself, for transfers and property access. It is only
+    /// valid while the manager the device was opened from is alive.
+    #[cfg(feature = "unsafe_raw")]
+    pub fn item(&self) -> &IWiaItem {
+        &self.item
+    }
+
+    pub fn is_folder(&self) -> bool {
+        self.flags & WiaItemTypeFolder != 0
+    }
+
+    /// Whether the item holds an image, as scan items and stored camera
+    /// pictures do.
+    pub fn is_image(&self) -> bool {
+        self.flags & WiaItemTypeImage != 0
+    }
+
+    /// Transfers the stored image into `output`, see [`download_image`].
+    pub fn download(&self, output: impl AsRef<Path>) -> Result<ScanResult> {
+        download_image(&self.item, output)
+    }
+}
+
+/// The state of a document feeder, decoded from
+/// `WIA_DPS_DOCUMENT_HANDLING_STATUS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeederStatus {
+    /// Paper is loaded and ready to be fed.
+    pub paper_loaded: bool,
+    pub paper_jam: bool,
+    /// The paper path cover is open.
+    pub cover_open: bool,
+}
+
+/// Reads the document handling capabilities from a device or item's
+/// property storage.
+///
+/// Without `WIA_DPS_DOCUMENT_HANDLING_CAPABILITIES`, falls back to
+/// `WIA_DPS_DOCUMENT_HANDLING_STATUS`, which can only confirm a source: each
+/// source it reports ready is `Some(true)`, and every other field stays
+/// `None`. If the status can't be read either, or the capabilities property
+/// has the wrong type, every field is `None` and
+/// [`detected`](Capabilities::detected) returns false.
+pub fn check_scanner_capabilities(props: &impl ItemProperties) -> Result<Capabilities> {
+    let mut capabilities = Capabilities::UNKNOWN;
+
+    log::debug!("Checking scanner capabilities...");
+
+    // Check document handling capabilities
+    match props.read_i4(WIA_DPS_DOCUMENT_HANDLING_CAPABILITIES) {
+        Ok(flags) => {
+            log::trace!("Capabilities value: {}", flags);
+
+            capabilities.feeder = Some((flags & (FEED as i32)) != 0);
+            capabilities.flatbed = Some((flags & (FLAT as i32)) != 0);
+            capabilities.duplex = Some((flags & (DUP as i32)) != 0);
+            capabilities.transparency = Some((flags & (FILM_TPA as i32)) != 0);
+
+            log::trace!("Detected capabilities: {:?}", capabilities);
+        }
+        Err(WiaError::UnsupportedPropertyType(vt)) => {
+            log::debug!("Unexpected capabilities property type: {} (expected VT_I4)", vt);
+        }
+        Err(e) => {
+            log::debug!("Failed to read document handling capabilities: {:?}", e);
+
+            // A source reporting ready is there; one that isn't may just be
+            // empty, so that says nothing either way
+            if let Ok(status) = props.read_i4(WIA_DPS_DOCUMENT_HANDLING_STATUS) {
+                log::trace!("Document handling status: {}", status);
+                let ready = |flag: u32| ((status as u32) & flag != 0).then_some(true);
+                capabilities.feeder = ready(FEED_READY);
+                capabilities.flatbed = ready(FLAT_READY);
+                capabilities.duplex = ready(DUP_READY);
+                capabilities.transparency = ready(FILM_TPA_READY);
+            }
+        }
+    }
+
+    if !capabilities.detected() {
+        log::warn!("Could not detect capabilities, leaving them unknown");
+    }
+
+    Ok(capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::options::IntentFlags;
+
+    fn scanner() -> MockDevice {
+        MockDevice::new("{6BDD1FC6-810F-11D0-BEC7-08002BE2092F}\\0000", "Test Scanner")
+    }
+
+    fn i4(value: u32) -> WiaValue {
+        WiaValue::I4(value as i32)
+    }
+
+    /// A 1x1 24-bit bitmap of the given gray level.
+    fn bmp(level: u8) -> Vec<u8> {
+        let mut bytes = b"BM".to_vec();
+        for value in [58u32, 0, 54, 40, 1, 1] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&24u16.to_le_bytes());
+        for value in [0u32, 4, 0, 0, 0, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[level, level, level, 0]);
+        bytes
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wia-rs-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn unset_options_write_nothing() {
+        let device = scanner();
+        prepare(&device, &ScanOptions::new()).unwrap();
+        assert_eq!(device.writes(), []);
+    }
+
+    #[test]
+    fn color_mode_and_resolution_are_written_to_the_scan_item() {
+        let device = scanner();
+        let options = ScanOptions::new().color_mode(ColorMode::Grayscale).dpi(300);
+        prepare(&device, &options).unwrap();
+
+        assert_eq!(
+            device.writes(),
+            [
+                (ItemLevel::Scan, WIA_IPS_CUR_INTENT, i4(WIA_INTENT_IMAGE_TYPE_GRAYSCALE)),
+                (ItemLevel::Scan, WIA_IPA_DATATYPE, i4(WIA_DATA_GRAYSCALE)),
+                (ItemLevel::Scan, WIA_IPS_XRES, WiaValue::I4(300)),
+                (ItemLevel::Scan, WIA_IPS_YRES, WiaValue::I4(300)),
+            ]
+        );
+    }
+
+    #[test]
+    fn intent_flags_share_the_color_intent_write() {
+        let device = scanner();
+        let options = ScanOptions::new()
+            .color_mode(ColorMode::Color)
+            .intent(IntentFlags::MINIMIZE_SIZE);
+        prepare(&device, &options).unwrap();
+
+        assert_eq!(
+            device.writes()[0],
+            (
+                ItemLevel::Scan,
+                WIA_IPS_CUR_INTENT,
+                i4(WIA_INTENT_IMAGE_TYPE_COLOR | WIA_INTENT_MINIMIZE_SIZE)
+            )
+        );
+    }
+
+    #[test]
+    fn jpeg_quality_becomes_an_intent_hint() {
+        let device = scanner();
+        prepare(&device, &ScanOptions::new().jpeg_quality(20)).unwrap();
+        assert_eq!(
+            device.writes(),
+            [
+                (ItemLevel::Scan, WIA_IPS_CUR_INTENT, i4(WIA_INTENT_MINIMIZE_SIZE)),
+                (ItemLevel::Scan, WIA_IPA_COMPRESSION, i4(WIA_COMPRESSION_JPEG)),
+            ]
+        );
+
+        let device = scanner();
+        let options = ScanOptions::new().color_mode(ColorMode::Color).jpeg_quality(90);
+        prepare(&device, &options).unwrap();
+        assert_eq!(
+            device.writes()[0],
+            (
+                ItemLevel::Scan,
+                WIA_IPS_CUR_INTENT,
+                i4(WIA_INTENT_IMAGE_TYPE_COLOR | WIA_INTENT_MAXIMIZE_QUALITY)
+            )
+        );
+    }
+
+    #[test]
+    fn source_and_page_count_go_to_both_items() {
+        let device = scanner();
+        let options = ScanOptions::new().source(ScanSource::Feeder).pages(2);
+        let configured = prepare(&device, &options).unwrap();
+
+        assert!(!configured.duplex);
+        assert_eq!(
+            device.writes(),
+            [
+                (ItemLevel::Root, WIA_DPS_PAGES, WiaValue::I4(2)),
+                (ItemLevel::Scan, WIA_IPS_PAGES, WiaValue::I4(2)),
+                (ItemLevel::Root, WIA_DPS_DOCUMENT_HANDLING_SELECT, i4(FEEDER)),
+                (ItemLevel::Scan, WIA_IPS_DOCUMENT_HANDLING_SELECT, i4(FEEDER)),
+                (ItemLevel::Root, WIA_DPS_TRANSPARENCY_SELECT, WiaValue::I4(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn auto_source_picks_the_loaded_feeder() {
+        let device = scanner().property(
+            ItemLevel::Root,
+            WIA_DPS_DOCUMENT_HANDLING_STATUS,
+            i4(FEED_READY),
+        );
+        let source = select_source(&device, &ScanOptions::new().source(ScanSource::Auto));
+        assert_eq!(source.unwrap(), Some(ScanSource::Feeder));
+    }
+
+    #[test]
+    fn duplex_is_refused_when_the_device_reports_none() {
+        let device = scanner().property(
+            ItemLevel::Root,
+            WIA_DPS_DOCUMENT_HANDLING_CAPABILITIES,
+            i4(FEED | FLAT),
+        );
+        let options = ScanOptions::new().source(ScanSource::FeederDuplex);
+
+        assert!(matches!(prepare(&device, &options), Err(WiaError::InvalidOption(_))));
+        assert_eq!(device.writes(), []);
+    }
+
+    #[test]
+    fn every_invalid_setting_is_reported_before_writing() {
+        let device = scanner()
+            .list(ItemLevel::Scan, WIA_IPA_DATATYPE, &[WIA_DATA_COLOR as i32])
+            .range(ItemLevel::Scan, WIA_IPS_XRES, 75, 600, 1);
+        let options = ScanOptions::new().color_mode(ColorMode::Grayscale).dpi(1200);
+
+        let Err(WiaError::InvalidSettings(errors)) = prepare(&device, &options) else {
+            panic!("expected the settings to be rejected");
+        };
+        let settings: Vec<&str> = errors.iter().map(|error| error.setting.as_str()).collect();
+        assert_eq!(settings, ["Data type", "Horizontal resolution"]);
+        assert_eq!(device.writes(), []);
+    }
+
+    #[test]
+    fn force_skips_validation() {
+        let device = scanner().list(ItemLevel::Scan, WIA_IPA_DATATYPE, &[WIA_DATA_COLOR as i32]);
+        let options = ScanOptions::new().color_mode(ColorMode::Grayscale).force(true);
+
+        prepare(&device, &options).unwrap();
+        // The data type is still checked on its own, leaving the intent
+        assert_eq!(
+            device.writes(),
+            [(ItemLevel::Scan, WIA_IPS_CUR_INTENT, i4(WIA_INTENT_IMAGE_TYPE_GRAYSCALE))]
+        );
+    }
+
+    #[test]
+    fn threshold_is_only_written_for_black_and_white() {
+        let device = scanner();
+        let configured = prepare(&device, &ScanOptions::new().threshold(128)).unwrap();
+        assert_eq!(device.writes(), []);
+        assert_eq!(configured.warnings.len(), 1);
+
+        let device = scanner();
+        let options = ScanOptions::new()
+            .color_mode(ColorMode::BlackAndWhite)
+            .threshold(128);
+        prepare(&device, &options).unwrap();
+        assert!(
+            device
+                .writes()
+                .contains(&(ItemLevel::Scan, WIA_IPS_THRESHOLD, WiaValue::I4(128)))
+        );
+    }
+
+    #[test]
+    fn missing_features_fail_unless_turned_off() {
+        let device = scanner();
+        assert!(matches!(
+            prepare(&device, &ScanOptions::new().deskew(true)),
+            Err(WiaError::FeatureUnsupported(_))
+        ));
+        prepare(&device, &ScanOptions::new().auto_exposure(false)).unwrap();
+        assert_eq!(device.writes(), []);
+
+        let device = scanner().property(ItemLevel::Scan, WIA_IPS_AUTO_DESKEW, WiaValue::I4(0));
+        prepare(&device, &ScanOptions::new().deskew(true)).unwrap();
+        assert_eq!(
+            device.writes(),
+            [(ItemLevel::Scan, WIA_IPS_AUTO_DESKEW, i4(WIA_AUTO_DESKEW_ON))]
+        );
+    }
+
+    #[test]
+    fn brightness_is_clamped_to_the_reported_range() {
+        let device = scanner().range(ItemLevel::Scan, WIA_IPS_BRIGHTNESS, -100, 100, 1);
+        let configured = prepare(&device, &ScanOptions::new().brightness(500)).unwrap();
+
+        assert_eq!(
+            device.writes(),
+            [(ItemLevel::Scan, WIA_IPS_BRIGHTNESS, WiaValue::I4(100))]
+        );
+        assert_eq!(configured.warnings.len(), 1);
+    }
+
+    #[test]
+    fn format_is_written_as_a_guid() {
+        let device = scanner();
+        prepare(&device, &ScanOptions::new().format(ScanFormat::Png)).unwrap();
+        assert_eq!(
+            device.writes(),
+            [(ItemLevel::Scan, WIA_IPA_FORMAT, WiaValue::Guid(WiaImgFmt_PNG))]
+        );
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn pdf_scanned_as_bmp_skips_compression() {
+        use crate::options::Compression;
+
+        let device =
+            scanner().rejects(ItemLevel::Scan, WIA_IPA_FORMAT, WiaValue::Guid(WiaImgFmt_PDFA));
+        let options = ScanOptions::new()
+            .format(ScanFormat::Pdf)
+            .compression(Compression::Jpeg);
+        let configured = prepare(&device, &options).unwrap();
+
+        assert!(configured.bmp_to_pdf);
+        assert_eq!(
+            device.writes(),
+            [(ItemLevel::Scan, WIA_IPA_FORMAT, WiaValue::Guid(WiaImgFmt_BMP))]
+        );
+        assert_eq!(configured.warnings.len(), 1);
+    }
+
+    #[test]
+    fn feed_stops_at_the_page_limit() {
+        let dir = temp_dir("feed-limit");
+        let device = scanner().page(bmp(0)).page(bmp(0)).page(bmp(0));
+        let options = ScanOptions::new().source(ScanSource::Feeder).pages(2);
+
+        let fed = feed_pages(&device, &options, |page| dir.join(format!("{}.bmp", page))).unwrap();
+        assert_eq!(fed.paths, [dir.join("1.bmp"), dir.join("2.bmp")]);
+        assert!(fed.paths.iter().all(|path| path.is_file()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn feed_ends_when_the_feeder_runs_out() {
+        let dir = temp_dir("feed-empty");
+        let device = scanner().page(bmp(0)).page(bmp(0));
+        let options = ScanOptions::new().source(ScanSource::Feeder);
+
+        let fed = feed_pages(&device, &options, |page| dir.join(format!("{}.bmp", page))).unwrap();
+        assert_eq!(fed.paths.len(), 2);
+        assert!(!fed.multi_feed);
+
+        let error = feed_pages(&device, &options, |page| dir.join(format!("{}.bmp", page)));
+        assert_eq!(error.err(), Some(WiaError::PaperEmpty));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn blank_pages_are_dropped_when_the_driver_cannot() {
+        let dir = temp_dir("feed-blank");
+        let device = scanner().page(bmp(255)).page(bmp(0)).page(bmp(255));
+        let options = ScanOptions::new()
+            .source(ScanSource::Feeder)
+            .skip_blank_pages(true);
+
+        let fed = feed_pages(&device, &options, |page| dir.join(format!("{}.bmp", page))).unwrap();
+        assert_eq!(fed.paths, [dir.join("1.bmp")]);
+        assert_eq!(fed.blank_skipped, 2);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lamp_control_ignores_root_properties_with_the_same_id() {
+        // WIA_IPS_LAMP's ID on the root item is a sheet size
+        let device = scanner().property(
+            ItemLevel::Root,
+            WIA_DPS_MIN_VERTICAL_SHEET_FEED_SIZE,
+            WiaValue::I4(500),
+        );
+
+        assert!(matches!(device.lamp_state(), Err(WiaError::FeatureUnsupported(_))));
+        assert!(matches!(device.set_lamp(true), Err(WiaError::FeatureUnsupported(_))));
+        assert_eq!(device.writes(), []);
+    }
+
+    #[test]
+    fn lamp_is_switched_on_the_scan_item() {
+        let device = scanner().property(ItemLevel::Scan, WIA_IPS_LAMP, i4(WIA_LAMP_OFF));
+        assert!(!device.lamp_state().unwrap());
+
+        device.set_lamp(true).unwrap();
+        assert!(device.lamp_state().unwrap());
+        assert_eq!(device.writes(), [(ItemLevel::Scan, WIA_IPS_LAMP, i4(WIA_LAMP_ON))]);
+    }
+
+    #[test]
+    fn memory_transfers_apply_the_options() {
+        let device = scanner().page(b"BM".to_vec());
+        let data = device.transfer(&ScanOptions::new().dpi(150)).unwrap();
+
+        assert_eq!(data, b"BM");
+        assert_eq!(device.property_value(ItemLevel::Scan, WIA_IPS_XRES), WiaValue::I4(150));
+    }
+
+    #[test]
+    fn memory_transfers_switch_the_medium_before_the_format() {
+        let device = scanner()
+            .property(ItemLevel::Scan, WIA_IPA_TYMED, WiaValue::I4(TYMED_FILE.0))
+            .page(bmp(0));
+        device.transfer(&ScanOptions::new().format(ScanFormat::Bmp)).unwrap();
+
+        assert_eq!(
+            device.writes(),
+            [
+                (ItemLevel::Scan, WIA_IPA_TYMED, i4(TYMED_CALLBACK)),
+                (ItemLevel::Scan, WIA_IPA_FORMAT, WiaValue::Guid(WiaImgFmt_BMP)),
+                (ItemLevel::Scan, WIA_IPA_TYMED, WiaValue::I4(TYMED_FILE.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_memory_transfers_restore_the_medium() {
+        let device = scanner()
+            .property(ItemLevel::Scan, WIA_IPA_TYMED, WiaValue::I4(TYMED_FILE.0))
+            .read_only(ItemLevel::Scan, WIA_IPA_FORMAT)
+            .page(bmp(0));
+
+        assert!(device.transfer(&ScanOptions::new().format(ScanFormat::Png)).is_err());
+        assert_eq!(
+            device.property_value(ItemLevel::Scan, WIA_IPA_TYMED),
+            WiaValue::I4(TYMED_FILE.0)
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use std::path::Path;
+
+use crate::{Result, WiaError};
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+// Used when a bitmap doesn't record its resolution
+const DEFAULT_DPI: u32 = 96;
+// Pages at least this bright on average, as a share of white, are blank
+const BLANK_BRIGHTNESS: f64 = 0.97;
+
+/// How a [`Page`]'s samples are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PixelKind {
+    /// One bit per pixel, 0 is black.
+    Bilevel,
+    /// One byte per pixel, 0 is black.
+    Gray,
+    /// Three bytes per pixel in R, G, B order.
+    Rgb,
+}
+
+impl PixelKind {
+    fn row_bytes(self, width: u32) -> usize {
+        let width = width as usize;
+        match self {
+            PixelKind::Bilevel => width.div_ceil(8),
+            PixelKind::Gray => width,
+            PixelKind::Rgb => width * 3,
+        }
+    }
+}
+
+/// A decoded page, stored top-down with rows padded to a whole byte, which
+/// is the layout both TIFF and PDF expect for uncompressed images.
+pub(crate) struct Page {
+    width: u32,
+    height: u32,
+    dpi_x: u32,
+    dpi_y: u32,
+    kind: PixelKind,
+    data: Vec<u8>,
+}
+
+impl Page {
+    /// Whether the page is close enough to plain white to be the blank back
+    /// of a sheet.
+    pub(crate) fn is_blank(&self) -> bool {
+        let row_bytes = self.kind.row_bytes(self.width);
+        let pixels = self.width as u64 * self.height as u64;
+        if pixels == 0 {
+            return true;
+        }
+
+        let brightness = match self.kind {
+            PixelKind::Bilevel => {
+                let white: u64 = self
+                    .data
+                    .chunks_exact(row_bytes)
+                    .map(|row| {
+                        // The padding bits past the last pixel don't count
+                        (0..self.width as usize)
+                            .filter(|x| row[x / 8] & (0x80 >> (x % 8)) != 0)
+                            .count() as u64
+                    })
+                    .sum();
+                white as f64 / pixels as f64
+            }
+            PixelKind::Gray | PixelKind::Rgb => {
+                let total: u64 = self.data.iter().map(|&sample| sample as u64).sum();
+                total as f64 / (self.data.len().max(1) as f64 * 255.0)
+            }
+        };
+        brightness >= BLANK_BRIGHTNESS
+    }
+}
+
+fn unsupported(message: &str) -> WiaError {
+    WiaError::UnsupportedImage(message.to_string())
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| unsupported("Truncated bitmap header"))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| unsupported("Truncated bitmap header"))
+}
+
+fn dpi_from_pels_per_meter(pels: u32) -> u32 {
+    match (pels as f64 * 0.0254).round() as u32 {
+        0 => DEFAULT_DPI,
+        dpi => dpi,
+    }
+}
+
+/// Decodes an uncompressed 1, 8, 24 or 32-bit BMP file, the format drivers
+/// are required to support.
+pub(crate) fn decode_bmp(bytes: &[u8]) -> Result<Page> {
+    if bytes.get(..2) != Some(b"BM") {
+        return Err(unsupported("Not a BMP file"));
+    }
+
+    let pixels_offset = read_u32(bytes, 10)? as usize;
+    let header_size = read_u32(bytes, 14)? as usize;
+    let width = read_u32(bytes, 18)? as i32;
+    let height = read_u32(bytes, 22)? as i32;
+    let bit_count = read_u16(bytes, 28)?;
+    let compression = read_u32(bytes, 30)?;
+    let dpi_x = dpi_from_pels_per_meter(read_u32(bytes, 38)?);
+    let dpi_y = dpi_from_pels_per_meter(read_u32(bytes, 42)?);
+    let colors_used = read_u32(bytes, 46)? as usize;
+
+    if width <= 0 || height == 0 {
+        return Err(unsupported("Bitmap has no pixels"));
+    }
+    if compression != BI_RGB && !(compression == BI_BITFIELDS && bit_count == 32) {
+        return Err(WiaError::UnsupportedImage(format!(
+            "Compressed bitmaps are not supported (compression {})",
+            compression
+        )));
+    }
+
+    // Negative heights mean the rows are already stored top-down
+    let top_down = height < 0;
+    let (width, height) = (width as u32, height.unsigned_abs());
+
+    let palette: Vec<[u8; 3]> = if bit_count <= 8 {
+        let count = if colors_used == 0 {
+            1 << bit_count
+        } else {
+            colors_used
+        };
+        let start = 14 + header_size;
+        let table = bytes
+            .get(start..start + count * 4)
+            .ok_or_else(|| unsupported("Truncated bitmap palette"))?;
+        table.chunks_exact(4).map(|c| [c[2], c[1], c[0]]).collect()
+    } else {
+        Vec::new()
+    };
+    let is_gray = palette.iter().all(|[r, g, b]| r == g && g == b);
+    let luma = |[r, g, b]: [u8; 3]| (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+
+    let kind = match bit_count {
+        1 => PixelKind::Bilevel,
+        8 if is_gray => PixelKind::Gray,
+        8 | 24 | 32 => PixelKind::Rgb,
+        _ => {
+            return Err(WiaError::UnsupportedImage(format!(
+                "{}-bit bitmaps are not supported",
+                bit_count
+            )));
+        }
+    };
+
+    let stride = (width as usize * bit_count as usize).div_ceil(32) * 4;
+    let pixels = bytes
+        .get(pixels_offset..pixels_offset + stride * height as usize)
+        .ok_or_else(|| unsupported("Truncated bitmap pixel data"))?;
+
+    // A palette whose first entry is the lighter one means a set bit is
+    // black, the reverse of the 0-is-black convention pages are stored in
+    let invert =
+        kind == PixelKind::Bilevel && palette.len() >= 2 && luma(palette[0]) > luma(palette[1]);
+
+    let mut data = Vec::with_capacity(kind.row_bytes(width) * height as usize);
+    for y in 0..height as usize {
+        let row_index = if top_down { y } else { height as usize - 1 - y };
+        let row = &pixels[row_index * stride..(row_index + 1) * stride];
+
+        match (bit_count, kind) {
+            (1, _) => {
+                let row = &row[..kind.row_bytes(width)];
+                if invert {
+                    data.extend(row.iter().map(|b| !b));
+                } else {
+                    data.extend_from_slice(row);
+                }
+            }
+            (8, PixelKind::Gray) => {
+                data.extend(
+                    row[..width as usize]
+                        .iter()
+                        .map(|&i| palette.get(i as usize).map_or(0, |color| color[0])),
+                );
+            }
+            (8, _) => {
+                for &i in &row[..width as usize] {
+                    data.extend_from_slice(&palette.get(i as usize).copied().unwrap_or_default());
+                }
+            }
+            (bits, _) => {
+                let step = bits as usize / 8;
+                for pixel in row[..width as usize * step].chunks_exact(step) {
+                    data.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+                }
+            }
+        }
+    }
+
+    Ok(Page {
+        width,
+        height,
+        dpi_x,
+        dpi_y,
+        kind,
+        data,
+    })
+}
+
+/// Writes the pages as a single multi-page TIFF, one uncompressed image
+/// file directory per page.
+pub(crate) fn write_tiff(pages: &[Page], path: &Path) -> Result<()> {
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+    const RATIONAL: u16 = 5;
+
+    let mut out: Vec<u8> = Vec::new();
+    out.extend_from_slice(b"II*\0");
+    // Where the offset of the next IFD goes, patched as each page is added
+    let mut next_ifd_at = out.len();
+    out.extend_from_slice(&0u32.to_le_bytes());
+
+    for (index, page) in pages.iter().enumerate() {
+        let samples: u16 = if page.kind == PixelKind::Rgb { 3 } else { 1 };
+        let bits: u16 = if page.kind == PixelKind::Bilevel {
+            1
+        } else {
+            8
+        };
+        let photometric: u16 = if page.kind == PixelKind::Rgb { 2 } else { 1 };
+
+        // Values too large for an IFD entry go before the image data
+        let bits_at = out.len() as u32;
+        for _ in 0..samples {
+            out.extend_from_slice(&bits.to_le_bytes());
+        }
+        let x_res_at = out.len() as u32;
+        out.extend_from_slice(&page.dpi_x.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+        let y_res_at = out.len() as u32;
+        out.extend_from_slice(&page.dpi_y.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes());
+
+        let strip_at = out.len() as u32;
+        out.extend_from_slice(&page.data);
+        if !out.len().is_multiple_of(2) {
+            out.push(0);
+        }
+
+        let bits_value = if samples == 1 { bits as u32 } else { bits_at };
+        let page_number = index as u32 | (pages.len() as u32) << 16;
+        // Entries must be sorted by tag
+        let entries: [(u16, u16, u32, u32); 14] = [
+            (254, LONG, 1, 2),
+            (256, LONG, 1, page.width),
+            (257, LONG, 1, page.height),
+            (258, SHORT, samples as u32, bits_value),
+            (259, SHORT, 1, 1),
+            (262, SHORT, 1, photometric as u32),
+            (273, LONG, 1, strip_at),
+            (277, SHORT, 1, samples as u32),
+            (278, LONG, 1, page.height),
+            (279, LONG, 1, page.data.len() as u32),
+            (282, RATIONAL, 1, x_res_at),
+            (283, RATIONAL, 1, y_res_at),
+            (296, SHORT, 1, 2),
+            (297, SHORT, 2, page_number),
+        ];
+
+        let ifd_at = out.len() as u32;
+        out[next_ifd_at..next_ifd_at + 4].copy_from_slice(&ifd_at.to_le_bytes());
+
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (tag, field_type, count, value) in entries {
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&field_type.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        next_ifd_at = out.len();
+        out.extend_from_slice(&0u32.to_le_bytes());
+    }
+
+    std::fs::write(path, out).map_err(|e| WiaError::Io(e.to_string()))
+}
+
+/// Writes the pages as a PDF, see [`encode_pdf`].
+pub(crate) fn write_pdf(pages: &[Page], path: &Path) -> Result<()> {
+    std::fs::write(path, encode_pdf(pages)).map_err(|e| WiaError::Io(e.to_string()))
+}
+
+/// Encodes the pages as a PDF, one uncompressed image per page sized to the
+/// page's resolution. No text layer is added.
+pub(crate) fn encode_pdf(pages: &[Page]) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+    let mut offsets: Vec<usize> = Vec::new();
+    // Objects 1 and 2 are the catalog and page tree, then three per page
+    let page_object = |index: usize| 3 + index * 3;
+
+    out.extend_from_slice(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n");
+
+    offsets.push(out.len());
+    out.extend_from_slice(b"1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n");
+
+    let kids: Vec<String> = (0..pages.len())
+        .map(|index| format!("{} 0 R", page_object(index)))
+        .collect();
+    offsets.push(out.len());
+    out.extend_from_slice(
+        format!(
+            "2 0 obj\n<< /Type /Pages /Kids [{}] /Count {} >>\nendobj\n",
+            kids.join(" "),
+            pages.len()
+        )
+        .as_bytes(),
+    );
+
+    for (index, page) in pages.iter().enumerate() {
+        let object = page_object(index);
+        let points_wide = page.width as f64 * 72.0 / page.dpi_x as f64;
+        let points_high = page.height as f64 * 72.0 / page.dpi_y as f64;
+        let content = format!(
+            "q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q",
+            points_wide, points_high
+        );
+        let (color_space, bits) = match page.kind {
+            PixelKind::Bilevel => ("DeviceGray", 1),
+            PixelKind::Gray => ("DeviceGray", 8),
+            PixelKind::Rgb => ("DeviceRGB", 8),
+        };
+
+        offsets.push(out.len());
+        out.extend_from_slice(
+            format!(
+                "{} 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+             /Resources << /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>\nendobj\n",
+                object,
+                points_wide,
+                points_high,
+                object + 2,
+                object + 1
+            )
+            .as_bytes(),
+        );
+
+        offsets.push(out.len());
+        out.extend_from_slice(
+            format!(
+                "{} 0 obj\n<< /Length {} >>\nstream\n{}\nendstream\nendobj\n",
+                object + 1,
+                content.len(),
+                content
+            )
+            .as_bytes(),
+        );
+
+        offsets.push(out.len());
+        out.extend_from_slice(
+            format!(
+                "{} 0 obj\n<< /Type /XObject /Subtype /Image /Width {} /Height {} \
+             /ColorSpace /{} /BitsPerComponent {} /Length {} >>\nstream\n",
+                object + 2,
+                page.width,
+                page.height,
+                color_space,
+                bits,
+                page.data.len()
+            )
+            .as_bytes(),
+        );
+        out.extend_from_slice(&page.data);
+        out.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    let xref_at = out.len();
+    out.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes(),
+    );
+    for offset in &offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            offsets.len() + 1,
+            xref_at
+        )
+        .as_bytes(),
+    );
+
+    out
+}
@@ -0,0 +1,102 @@
+use std::marker::PhantomData;
+
+use windows::Win32::Devices::ImageAcquisition::*;
+
+use crate::util::{
+    read_bstr_property, read_i4_property, read_optional_bstr_property, read_wide_property,
+};
+use crate::{DeviceId, DeviceInfo, DeviceType, Result, WIAScanManager, WiaError};
+
+/// A lazy iterator over the local WIA devices, returned by
+/// [`WIAScanManager::devices`].
+///
+/// Each step fetches a single device from the enumerator without reading
+/// any of its properties. The iterator stops after the first error.
+pub struct Devices<'m> {
+    enumerator: Option<IEnumWIA_DEV_INFO>,
+    _manager: PhantomData<&'m WIAScanManager>,
+}
+
+impl<'m> Devices<'m> {
+    pub(crate) fn new(enumerator: IEnumWIA_DEV_INFO) -> Self {
+        Devices {
+            enumerator: Some(enumerator),
+            _manager: PhantomData,
+        }
+    }
+}
+
+impl<'m> Iterator for Devices<'m> {
+    type Item = Result<DeviceEntry<'m>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let enumerator = self.enumerator.as_ref()?;
+
+        let mut dev_info: Option<IWiaPropertyStorage> = None;
+        let mut fetched = 0;
+        // Running out of devices is S_FALSE with nothing fetched, not an error
+        let result = unsafe { enumerator.Next(1, &mut dev_info, &mut fetched) };
+
+        match (result, dev_info) {
+            (Ok(()), Some(props)) if fetched > 0 => Some(Ok(DeviceEntry {
+                props,
+                _manager: PhantomData,
+            })),
+            (Ok(()), _) => {
+                self.enumerator = None;
+                None
+            }
+            (Err(e), _) => {
+                self.enumerator = None;
+                Some(Err(WiaError::from(e)))
+            }
+        }
+    }
+}
+
+/// A device yielded by [`Devices`]. Properties are read from the driver on
+/// each call, so an offline network device only costs time when asked about.
+pub struct DeviceEntry<'m> {
+    props: IWiaPropertyStorage,
+    _manager: PhantomData<&'m WIAScanManager>,
+}
+
+impl DeviceEntry<'_> {
+    pub fn id(&self) -> Result<DeviceId> {
+        read_wide_property(&self.props, WIA_DIP_DEV_ID).map(DeviceId)
+    }
+
+    pub fn name(&self) -> Result<String> {
+        read_bstr_property(&self.props, WIA_DIP_DEV_NAME)
+    }
+
+    pub fn description(&self) -> Result<String> {
+        read_bstr_property(&self.props, WIA_DIP_DEV_DESC)
+    }
+
+    /// The kind of device, [`DeviceType::Other`] if the driver doesn't say.
+    pub fn device_type(&self) -> DeviceType {
+        read_i4_property(&self.props, WIA_DIP_DEV_TYPE)
+            .map(DeviceType::from_dev_type)
+            .unwrap_or(DeviceType::Other(0))
+    }
+
+    /// Reads every property [`DeviceInfo`] holds.
+    pub fn info(&self) -> Result<DeviceInfo> {
+        Ok(DeviceInfo {
+            id: self.id()?,
+            name: self.name()?,
+            description: self.description()?,
+            device_type: self.device_type(),
+            vendor: read_optional_bstr_property(&self.props, WIA_DIP_VEND_DESC),
+            server_name: read_optional_bstr_property(&self.props, WIA_DIP_SERVER_NAME),
+            port_name: read_optional_bstr_property(&self.props, WIA_DIP_PORT_NAME),
+            driver_version: read_optional_bstr_property(&self.props, WIA_DIP_DRIVER_VERSION),
+        })
+    }
+
+    /// The device information property storage.
+    pub fn properties(&self) -> &IWiaPropertyStorage {
+        &self.props
+    }
+}
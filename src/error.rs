@@ -0,0 +1,489 @@
+use windows::{Win32::Devices::ImageAcquisition::*, core::*};
+
+use crate::{DeviceId, DeviceInfo};
+
+/// Errors reported by WIA devices, the COM runtime, or this crate itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WiaError {
+    NoDeviceAvailable,
+    Busy,
+    CoverOpen,
+    DeviceCommunication,
+    DeviceLocked,
+    ExceptionInDriver,
+    GeneralError,
+    IncorrectHardwareSetting,
+    InvalidCommand,
+    InvalidDriverResponse,
+    ItemDeleted,
+    LampOff,
+    MaximumPrinterEndorserCounter,
+    MultiFeed,
+    Offline,
+    PaperEmpty,
+    PaperJam,
+    PaperProblem,
+    WarmingUp,
+    UserIntervention,
+    /// A WIA HRESULT that isn't in the known error table.
+    Unknown(HRESULT),
+    /// Any other COM failure.
+    Com(Error),
+    /// No device with the given ID is installed anymore.
+    DeviceNotFound(DeviceId),
+    /// More than one scanner is installed where exactly one was expected.
+    MultipleDevices(Vec<DeviceInfo>),
+    /// More than one device matched a lookup that expected exactly one.
+    AmbiguousDevice(Vec<DeviceInfo>),
+    /// The feeder pulled in more than one sheet at once partway through a
+    /// multi-page scan. The `pages` scanned before it were kept, so the
+    /// batch can be resumed from the sheets still in the feeder.
+    MultiFeedAfter { pages: u32 },
+    /// The device didn't respond within the requested time.
+    Timeout,
+    /// The device didn't expose an item to scan from.
+    NoScannerItem,
+    /// The device has no child item with the given name.
+    ItemNotFound(String),
+    /// A scan option or property value the crate or the driver won't accept.
+    InvalidOption(String),
+    /// A property value that isn't among the values the driver lists as
+    /// valid for it.
+    UnsupportedValue {
+        property: String,
+        value: i32,
+        supported: Vec<i32>,
+    },
+    /// The device doesn't have a feature an option asked for.
+    FeatureUnsupported(String),
+    /// Settings the driver reports it won't accept, found by checking every
+    /// option before anything is written.
+    InvalidSettings(Vec<SettingError>),
+    /// A property holds a variant type `read_property` can't convert.
+    UnsupportedPropertyType(u16),
+    /// A transferred image the crate can't decode.
+    UnsupportedImage(String),
+    /// Reading or writing a file failed.
+    Io(String),
+}
+
+impl WiaError {
+    /// The HRESULT behind the error, for matching on specific codes. `None`
+    /// for errors raised by the crate itself rather than WIA or COM.
+    ///
+    /// ```
+    /// use wia_rs::WiaError;
+    /// use windows::Win32::Devices::ImageAcquisition::{WIA_ERROR_BUSY, WIA_ERROR_COVER_OPEN};
+    ///
+    /// let retry = |error: &WiaError| match error.code() {
+    ///     Some(code) if code == WIA_ERROR_BUSY => true,
+    ///     Some(code) if code == WIA_ERROR_COVER_OPEN => false,
+    ///     _ => error.is_transient(),
+    /// };
+    /// assert!(retry(&WiaError::Busy));
+    /// assert!(!retry(&WiaError::CoverOpen));
+    /// assert_eq!(WiaError::Timeout.code(), None);
+    /// ```
+    pub fn code(&self) -> Option<HRESULT> {
+        match self {
+            WiaError::Unknown(code) => Some(*code),
+            WiaError::Com(err) => Some(err.code()),
+            WiaError::MultiFeedAfter { .. } => Some(WIA_ERROR_MULTI_FEED),
+            _ => ERROR_CODES
+                .iter()
+                .find(|(_, (error, _, _))| error == self)
+                .map(|(code, _)| *code),
+        }
+    }
+
+    /// Whether the error usually clears up by itself if the operation is
+    /// tried again shortly after.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            WiaError::Busy | WiaError::WarmingUp | WiaError::DeviceCommunication
+        )
+    }
+}
+
+impl std::fmt::Display for WiaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((code, (_, name, desc))) = ERROR_CODES.iter().find(|(_, (error, _, _))| error == self) {
+            return write!(f, "{} - {} - {}", code, name, desc);
+        }
+
+        match self {
+            WiaError::DeviceNotFound(id) => write!(f, "No WIA device with ID {}", id),
+            WiaError::MultipleDevices(devices) => {
+                let names: Vec<&str> = devices.iter().map(|d| d.name.as_str()).collect();
+                write!(f, "Multiple scanners found: {}", names.join(", "))
+            }
+            WiaError::AmbiguousDevice(candidates) => {
+                let names: Vec<&str> = candidates.iter().map(|d| d.name.as_str()).collect();
+                write!(f, "Multiple devices match: {}", names.join(", "))
+            }
+            WiaError::MultiFeedAfter { pages } => write!(
+                f,
+                "Multiple sheets were fed at once after {} page(s) were scanned",
+                pages
+            ),
+            WiaError::Timeout => write!(f, "Timed out waiting for the device"),
+            WiaError::NoScannerItem => write!(f, "No scanner item found"),
+            WiaError::ItemNotFound(name) => write!(f, "No item named {}", name),
+            WiaError::InvalidOption(message) => write!(f, "{}", message),
+            WiaError::UnsupportedValue {
+                property,
+                value,
+                supported,
+            } => write!(
+                f,
+                "{} {} is not supported, expected one of {:?}",
+                property, value, supported
+            ),
+            WiaError::FeatureUnsupported(feature) => {
+                write!(f, "{} is not supported by the device", feature)
+            }
+            WiaError::InvalidSettings(errors) => {
+                let messages: Vec<String> = errors.iter().map(|e| e.error.to_string()).collect();
+                write!(f, "Invalid settings: {}", messages.join("; "))
+            }
+            WiaError::UnsupportedPropertyType(vt) => {
+                write!(f, "Unsupported property type: {}", vt)
+            }
+            WiaError::UnsupportedImage(message) => write!(f, "Unsupported image: {}", message),
+            WiaError::Io(message) => write!(f, "I/O error: {}", message),
+            WiaError::Unknown(code) => {
+                // WIA codes often have no system message text
+                let message = code.message();
+                if message.is_empty() {
+                    write!(f, "{} - Unknown WIA error", code)
+                } else {
+                    write!(f, "{} - {}", code, message)
+                }
+            }
+            WiaError::Com(err) => write!(f, "{} - {}", err.code(), err.message()),
+            // Every other variant is in ERROR_CODES and was handled above
+            _ => write!(f, "Unknown error"),
+        }
+    }
+}
+
+/// One setting in [`WiaError::InvalidSettings`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingError {
+    /// The setting, such as `"Bit depth"`.
+    pub setting: String,
+    /// Why the driver won't take it, usually [`WiaError::UnsupportedValue`]
+    /// or, for a value outside a range, [`WiaError::InvalidOption`].
+    pub error: WiaError,
+}
+
+/// Informational codes WIA returns alongside success. None of them mean
+/// the operation failed, so they never show up as a [`WiaError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WiaStatus {
+    EndOfMedia,
+    WarmingUp,
+    Calibrating,
+    ReservingNetworkDevice,
+    NetworkDeviceReserved,
+    Clear,
+    SkipItem,
+    NotHandled,
+    ChangeDevice,
+}
+
+impl WiaStatus {
+    /// Classifies a success code from a WIA call, `None` for plain `S_OK`
+    /// and anything else that isn't a WIA status.
+    pub fn from_hresult(code: HRESULT) -> Option<Self> {
+        STATUS_CODES
+            .iter()
+            .find(|(status_code, _)| *status_code == code)
+            .map(|(_, (status, _, _))| *status)
+    }
+}
+
+impl std::fmt::Display for WiaStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match STATUS_CODES.iter().find(|(_, (status, _, _))| status == self) {
+            Some((code, (_, name, desc))) => write!(f, "{} - {} - {}", code, name, desc),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl std::error::Error for WiaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WiaError::Com(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// WIA_S_NO_DEVICE_AVAILABLE shares its code with
+// WIA_ERROR_MAXIMUM_PRINTER_ENDORSER_COUNTER and is by far the more common
+// of the two, so it is listed first.
+const ERROR_CODES: [(HRESULT, (WiaError, &str, &str)); 20] = [
+    (
+        WIA_S_NO_DEVICE_AVAILABLE,
+        (
+            WiaError::NoDeviceAvailable,
+            "WIA_S_NO_DEVICE_AVAILABLE",
+            "No scanner device was found. Make sure the device is online, connected to the PC, and has the correct driver installed on the PC.",
+        ),
+    ),
+    (
+        WIA_ERROR_BUSY,
+        (
+            WiaError::Busy,
+            "WIA_ERROR_BUSY",
+            "The device is busy. Close any apps that are using this device or wait for it to finish and then try again.",
+        ),
+    ),
+    (
+        WIA_ERROR_COVER_OPEN,
+        (
+            WiaError::CoverOpen,
+            "WIA_ERROR_COVER_OPEN",
+            "One or more of the device’s cover is open.",
+        ),
+    ),
+    (
+        WIA_ERROR_DEVICE_COMMUNICATION,
+        (
+            WiaError::DeviceCommunication,
+            "WIA_ERROR_DEVICE_COMMUNICATION",
+            "Communication with the WIA device failed. Make sure that the device is powered on and connected to the PC. If the problem persists, disconnect and reconnect the device.",
+        ),
+    ),
+    (
+        WIA_ERROR_DEVICE_LOCKED,
+        (
+            WiaError::DeviceLocked,
+            "WIA_ERROR_DEVICE_LOCKED",
+            "The device is locked. Close any apps that are using this device or wait for it to finish and then try again.",
+        ),
+    ),
+    (
+        WIA_ERROR_EXCEPTION_IN_DRIVER,
+        (
+            WiaError::ExceptionInDriver,
+            "WIA_ERROR_EXCEPTION_IN_DRIVER",
+            "The device driver threw an exception.",
+        ),
+    ),
+    (
+        WIA_ERROR_GENERAL_ERROR,
+        (
+            WiaError::GeneralError,
+            "WIA_ERROR_GENERAL_ERROR",
+            "An unknown error has occurred with the WIA device.",
+        ),
+    ),
+    (
+        WIA_ERROR_INCORRECT_HARDWARE_SETTING,
+        (
+            WiaError::IncorrectHardwareSetting,
+            "WIA_ERROR_INCORRECT_HARDWARE_SETTING",
+            "There is an incorrect setting on the WIA device.",
+        ),
+    ),
+    (
+        WIA_ERROR_INVALID_COMMAND,
+        (
+            WiaError::InvalidCommand,
+            "WIA_ERROR_INVALID_COMMAND",
+            "The device doesn't support this command.",
+        ),
+    ),
+    (
+        WIA_ERROR_INVALID_DRIVER_RESPONSE,
+        (
+            WiaError::InvalidDriverResponse,
+            "WIA_ERROR_INVALID_DRIVER_RESPONSE",
+            "The response from the driver is invalid.",
+        ),
+    ),
+    (
+        WIA_ERROR_ITEM_DELETED,
+        (
+            WiaError::ItemDeleted,
+            "WIA_ERROR_ITEM_DELETED",
+            "The WIA device was deleted. It's no longer available.",
+        ),
+    ),
+    (
+        WIA_ERROR_LAMP_OFF,
+        (
+            WiaError::LampOff,
+            "WIA_ERROR_LAMP_OFF",
+            "The scanner's lamp is off.",
+        ),
+    ),
+    (
+        WIA_ERROR_MAXIMUM_PRINTER_ENDORSER_COUNTER,
+        (
+            WiaError::MaximumPrinterEndorserCounter,
+            "WIA_ERROR_MAXIMUM_PRINTER_ENDORSER_COUNTER",
+            "A scan job was interrupted because an Imprinter/Endorser item reached the maximum valid value for WIA_IPS_PRINTER_ENDORSER_COUNTER, and was reset to 0. This feature is available with Windows 8 and later versions of Windows.",
+        ),
+    ),
+    (
+        WIA_ERROR_MULTI_FEED,
+        (
+            WiaError::MultiFeed,
+            "WIA_ERROR_MULTI_FEED",
+            "A scan error occurred because of a multiple page feed condition. This feature is available with Windows 8 and later versions of Windows.",
+        ),
+    ),
+    (
+        WIA_ERROR_OFFLINE,
+        (
+            WiaError::Offline,
+            "WIA_ERROR_OFFLINE",
+            "The device is offline. Make sure the device is powered on and connected to the PC.",
+        ),
+    ),
+    (
+        WIA_ERROR_PAPER_EMPTY,
+        (
+            WiaError::PaperEmpty,
+            "WIA_ERROR_PAPER_EMPTY",
+            "There are no documents in the document feeder.",
+        ),
+    ),
+    (
+        WIA_ERROR_PAPER_JAM,
+        (
+            WiaError::PaperJam,
+            "WIA_ERROR_PAPER_JAM",
+            "Paper is jammed in the scanner's document feeder.",
+        ),
+    ),
+    (
+        WIA_ERROR_PAPER_PROBLEM,
+        (
+            WiaError::PaperProblem,
+            "WIA_ERROR_PAPER_PROBLEM",
+            "An unspecified problem occurred with the scanner's document feeder.",
+        ),
+    ),
+    (
+        WIA_ERROR_WARMING_UP,
+        (
+            WiaError::WarmingUp,
+            "WIA_ERROR_WARMING_UP",
+            "The device is warming up.",
+        ),
+    ),
+    (
+        WIA_ERROR_USER_INTERVENTION,
+        (
+            WiaError::UserIntervention,
+            "WIA_ERROR_USER_INTERVENTION",
+            "There is a problem with the WIA device. Make sure that the device is turned on, online, and any cables are properly connected.",
+        ),
+    ),
+];
+
+const STATUS_CODES: [(HRESULT, (WiaStatus, &str, &str)); 9] = [
+    (
+        WIA_STATUS_END_OF_MEDIA,
+        (
+            WiaStatus::EndOfMedia,
+            "WIA_STATUS_END_OF_MEDIA",
+            "The last page in the document feeder was transferred.",
+        ),
+    ),
+    (
+        WIA_STATUS_WARMING_UP,
+        (
+            WiaStatus::WarmingUp,
+            "WIA_STATUS_WARMING_UP",
+            "The device is warming up.",
+        ),
+    ),
+    (
+        WIA_STATUS_CALIBRATING,
+        (
+            WiaStatus::Calibrating,
+            "WIA_STATUS_CALIBRATING",
+            "The device is calibrating.",
+        ),
+    ),
+    (
+        WIA_STATUS_RESERVING_NETWORK_DEVICE,
+        (
+            WiaStatus::ReservingNetworkDevice,
+            "WIA_STATUS_RESERVING_NETWORK_DEVICE",
+            "The network device is being reserved.",
+        ),
+    ),
+    (
+        WIA_STATUS_NETWORK_DEVICE_RESERVED,
+        (
+            WiaStatus::NetworkDeviceReserved,
+            "WIA_STATUS_NETWORK_DEVICE_RESERVED",
+            "The network device was reserved.",
+        ),
+    ),
+    (
+        WIA_STATUS_CLEAR,
+        (
+            WiaStatus::Clear,
+            "WIA_STATUS_CLEAR",
+            "A previous status message no longer applies.",
+        ),
+    ),
+    (
+        WIA_STATUS_SKIP_ITEM,
+        (
+            WiaStatus::SkipItem,
+            "WIA_STATUS_SKIP_ITEM",
+            "The item was skipped.",
+        ),
+    ),
+    (
+        WIA_STATUS_NOT_HANDLED,
+        (
+            WiaStatus::NotHandled,
+            "WIA_STATUS_NOT_HANDLED",
+            "The request was not handled, so the default handling applies.",
+        ),
+    ),
+    (
+        WIA_S_CHANGE_DEVICE,
+        (
+            WiaStatus::ChangeDevice,
+            "WIA_S_CHANGE_DEVICE",
+            "The user chose a different device.",
+        ),
+    ),
+];
+
+pub(crate) fn get_error(error_code: HRESULT) -> Option<WiaError> {
+    ERROR_CODES
+        .iter()
+        .find(|(code, _)| *code == error_code)
+        .map(|(_, (error, _, _))| error.clone())
+}
+
+// The facility code WIA uses for its HRESULTs (0x8021xxxx)
+const FACILITY_WIA: u32 = 0x21;
+
+impl From<Error> for WiaError {
+    /// Maps known WIA HRESULTs to their variant, other WIA codes to
+    /// [`WiaError::Unknown`] and anything else to [`WiaError::Com`].
+    fn from(err: Error) -> Self {
+        let code = err.code();
+        if let Some(error) = get_error(code) {
+            error
+        } else if (code.0 as u32 >> 16) & 0x7FF == FACILITY_WIA {
+            WiaError::Unknown(code)
+        } else {
+            WiaError::Com(err)
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::marker::PhantomData;
+
+use windows::core::IUnknown;
+
+use crate::{DeviceId, WIAScanManager};
+
+/// What happened to a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceEventKind {
+    Connected,
+    Disconnected,
+    /// The device's scan button was pressed.
+    ScanButton,
+}
+
+/// A device event, as delivered to the callback passed to
+/// [`WIAScanManager::subscribe_device_events`] or
+/// [`WIAScanManager::subscribe_scan_button`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceEvent {
+    pub kind: DeviceEventKind,
+    pub device_id: DeviceId,
+}
+
+/// Keeps a device event callback registered. Dropping it unregisters the
+/// callback.
+pub struct EventSubscription<'m> {
+    // Releasing the objects WIA hands back is what unregisters
+    _registrations: Vec<IUnknown>,
+    _manager: PhantomData<&'m WIAScanManager>,
+}
+
+impl EventSubscription<'_> {
+    pub(crate) fn new(registrations: Vec<IUnknown>) -> Self {
+        EventSubscription {
+            _registrations: registrations,
+            _manager: PhantomData,
+        }
+    }
+}
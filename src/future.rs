@@ -0,0 +1,69 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::{Result, WiaError};
+
+struct Shared<T> {
+    // A panic on the worker is carried over and resumed in `poll`
+    result: Option<thread::Result<Result<T>>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves to the result of work running on its own thread.
+pub(crate) struct ThreadFuture<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// Runs `work` on a new thread and returns a future for its result.
+///
+/// The thread is the only one that touches the COM objects `work` creates,
+/// so they never have to be `Send`. Dropping the future doesn't stop the
+/// work, it just discards the result.
+pub(crate) fn spawn<T, F>(name: &str, work: F) -> ThreadFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+
+    let worker_shared = Arc::clone(&shared);
+    let spawned = thread::Builder::new().name(name.to_string()).spawn(move || {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(work));
+
+        let mut shared = worker_shared.lock().unwrap_or_else(|e| e.into_inner());
+        shared.result = Some(result);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    });
+
+    if let Err(e) = spawned {
+        let message = format!("Failed to start the scan thread: {}", e);
+        shared.lock().unwrap_or_else(|e| e.into_inner()).result =
+            Some(Ok(Err(WiaError::Io(message))));
+    }
+
+    ThreadFuture { shared }
+}
+
+impl<T> Future for ThreadFuture<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().unwrap_or_else(|e| e.into_inner());
+        match shared.result.take() {
+            Some(Ok(result)) => Poll::Ready(result),
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
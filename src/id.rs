@@ -0,0 +1,61 @@
+use windows::core::BSTR;
+
+/// A WIA device ID, kept as the UTF-16 the driver reported.
+///
+/// Some drivers put characters in their IDs that don't survive a round
+/// trip through `String`, so IDs read from a device are never converted.
+/// Use [`Display`](std::fmt::Display) for a lossy, printable form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceId(pub(crate) Vec<u16>);
+
+impl DeviceId {
+    /// Wraps an ID given as UTF-16, without a trailing NUL.
+    pub fn from_wide(wide: &[u16]) -> Self {
+        DeviceId(wide.to_vec())
+    }
+
+    /// The ID as UTF-16, without a trailing NUL.
+    pub fn as_wide(&self) -> &[u16] {
+        &self.0
+    }
+
+    pub(crate) fn to_bstr(&self) -> BSTR {
+        BSTR::from_wide(&self.0)
+    }
+}
+
+impl std::fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from_utf16_lossy(&self.0))
+    }
+}
+
+impl From<&str> for DeviceId {
+    fn from(id: &str) -> Self {
+        DeviceId(id.encode_utf16().collect())
+    }
+}
+
+impl From<String> for DeviceId {
+    fn from(id: String) -> Self {
+        DeviceId::from(id.as_str())
+    }
+}
+
+impl From<&String> for DeviceId {
+    fn from(id: &String) -> Self {
+        DeviceId::from(id.as_str())
+    }
+}
+
+impl From<&DeviceId> for DeviceId {
+    fn from(id: &DeviceId) -> Self {
+        id.clone()
+    }
+}
+
+impl From<&BSTR> for DeviceId {
+    fn from(id: &BSTR) -> Self {
+        DeviceId::from_wide(id)
+    }
+}
@@ -1,346 +1,647 @@
-use std::collections::HashMap;
-use windows::{
-    Win32::{
-        Devices::ImageAcquisition::*,
-        System::{
-            Com::{
-                StructuredStorage::{
-                    PROPSPEC, PROPSPEC_0, PROPVARIANT, PRSPEC_PROPID, PropVariantClear,
-                },
-                *,
-            },
-            Variant::*,
-        },
-    },
-    core::*,
+//! Windows Image Acquisition (WIA) scanning from Rust.
+//!
+//! Everything starts from a [`WIAScanManager`], which initializes COM and
+//! connects to the WIA device manager:
+//!
+//! ```no_run
+//! use wia_rs::prelude::*;
+//!
+//! let manager = WIAScanManager::init()?;
+//! let devices = manager.enumerate_devices()?;
+//! let Some(info) = devices.first() else {
+//!     return Ok(());
+//! };
+//!
+//! let device = manager.open_device(&info.id)?;
+//! let source = if device.capabilities()?.feeder == Some(true) {
+//!     ScanSource::Feeder
+//! } else {
+//!     ScanSource::Flatbed
+//! };
+//!
+//! let result = device.scan_with_options(
+//!     &ScanOptions::new()
+//!         .source(source)
+//!         .dpi(300)
+//!         .format(ScanFormat::Png)
+//!         .output("scan.png"),
+//! )?;
+//! println!("Saved {} bytes to {}", result.bytes, result.path.display());
+//! # Ok::<(), WiaError>(())
+//! ```
+
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+use windows::Win32::{Devices::ImageAcquisition::*, System::Com::*};
+use windows::core::{BSTR, GUID};
+
+mod agent;
+mod backend;
+mod callback;
+mod com;
+mod device;
+mod document;
+mod enumerate;
+mod events;
+pub mod error;
+#[cfg(feature = "async")]
+mod future;
+mod id;
+#[cfg(any(test, feature = "testing"))]
+mod mock;
+mod options;
+#[cfg(feature = "serde")]
+mod profile;
+mod retry;
+mod session;
+mod settings;
+mod util;
+
+pub use agent::WiaAgent;
+pub use backend::{BackendDevice, BackendItem, ItemLevel, ItemProperties, WiaBackend};
+pub use com::ComThreadingModel;
+pub use device::{
+    Capabilities, FeederStatus, Resolutions, WiaDevice, WiaItemInfo, WiaItemNode,
+    check_scanner_capabilities, download_image,
+};
+pub use enumerate::{DeviceEntry, Devices};
+pub use events::{DeviceEvent, DeviceEventKind, EventSubscription};
+pub use options::{
+    AutoCrop, ColorMode, Compression, DocumentFormat, EffectiveSettings, IntentFlags, Orientation,
+    PageSize, Rotation, RotationProperty, ScanFormat, ScanOptions, ScanRegion, ScanResult,
+    ScanSettings, ScanSource,
+};
+#[cfg(feature = "serde")]
+pub use profile::{ScanProfile, load_profiles, save_profiles};
+pub use retry::RetryPolicy;
+pub use session::ScanSession;
+pub use settings::{
+    set_auto_crop, set_auto_exposure, set_bit_depth, set_blank_page_skipping, set_brightness,
+    set_brightness_normalized, set_color_mode, set_color_mode_with_intent, set_compression,
+    set_contrast, set_contrast_normalized, set_deskew, set_intent, set_multi_feed_detection,
+    set_orientation, set_page_size, set_preview, set_resolution, set_resolution_xy, set_rotation,
+    set_scan_region, set_threshold, supported_resolutions,
 };
+pub use error::{SettingError, WiaError, WiaStatus};
+pub use id::DeviceId;
+#[cfg(any(test, feature = "testing"))]
+pub use mock::{MockBackend, MockDevice};
+pub use util::{WiaValue, enumerate_properties, read_property};
+use callback::{CancelCallback, EventCallback};
+use retry::with_retry;
+
+/// The result type used throughout the crate.
+pub type Result<T> = std::result::Result<T, WiaError>;
+
+/// The types most consumers of the crate need.
+pub mod prelude {
+    pub use crate::{
+        Capabilities, ColorMode, DeviceId, DeviceInfo, DeviceType, DocumentFormat, ScanFormat,
+        ScanOptions, ScanResult, ScanSource, WIAScanManager, WiaDevice, WiaError,
+    };
+}
 
-mod util;
+/// Entry point of the crate. Owns the COM initialization of the calling
+/// thread and the connection to the WIA device manager.
+///
+/// # Threads
+///
+/// WIA interfaces belong to the COM apartment of the thread that created
+/// them, and the manager's COM initialization is counted per thread, so the
+/// manager and everything borrowed from it are neither `Send` nor `Sync`.
+/// To scan from async code or a thread pool, create the manager on a
+/// dedicated thread and send it work over a channel, or create one per
+/// thread where it's needed.
+///
+/// ```compile_fail
+/// let manager = wia_rs::WIAScanManager::init().unwrap();
+/// std::thread::spawn(move || manager.enumerate_devices());
+/// ```
+pub struct WIAScanManager {
+    // Released by hand in `Drop` so it goes away before `CoUninitialize`
+    device_manager: ManuallyDrop<IWiaDevMgr>,
+    // Whether this instance performed (or shares) our COM initialization.
+    // False when COM was already set up with another apartment model or the
+    // manager was attached, in which case the apartment isn't ours to tear down
+    owns_com: bool,
+    // Pins the manager to its thread even if the windows crate ever marks
+    // the interfaces above as agile
+    _thread_bound: PhantomData<*const ()>,
+}
+
+/// A WIA device as reported by the device manager's enumeration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub id: DeviceId,
+    pub name: String,
+    pub description: String,
+    pub device_type: DeviceType,
+    /// The vendor description. This and the fields below are `None` when the
+    /// driver doesn't report them.
+    pub vendor: Option<String>,
+    /// The machine the device is attached to, `"local"` for this one.
+    pub server_name: Option<String>,
+    /// The port the device is connected through, which tells apart two
+    /// devices of the same model.
+    pub port_name: Option<String>,
+    pub driver_version: Option<String>,
+}
 
-use util::{read_bstr_property, handle_error};
+/// The kind of hardware behind a WIA device, from `WIA_DIP_DEV_TYPE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    Scanner,
+    Camera,
+    Video,
+    /// Any other major type, or a driver that doesn't report one.
+    Other(i32),
+}
 
-struct WIAScanManager;
+impl DeviceType {
+    pub(crate) fn from_dev_type(dev_type: i32) -> Self {
+        // The major STI type lives in the high word, the subtype in the low one
+        match dev_type >> 16 {
+            1 => DeviceType::Scanner,
+            2 => DeviceType::Camera,
+            3 => DeviceType::Video,
+            other => DeviceType::Other(other),
+        }
+    }
+}
 
 impl Drop for WIAScanManager {
     fn drop(&mut self) {
         unsafe {
-            CoUninitialize();
+            ManuallyDrop::drop(&mut self.device_manager);
+            if self.owns_com {
+                com::release();
+            }
         }
     }
 }
 
 impl WIAScanManager {
-    pub fn init() -> std::result::Result<Self, String> {
+    /// Initializes COM for the calling thread as a multi-threaded apartment
+    /// and connects to the WIA device manager.
+    ///
+    /// If the thread was already initialized as a single-threaded apartment
+    /// (as GUI frameworks do), that apartment is used as is and left alone
+    /// when the manager is dropped. Managers on the same thread share one COM
+    /// initialization, which is only torn down when the last of them is
+    /// dropped, so calling `init` more than once is fine:
+    ///
+    /// ```
+    /// use wia_rs::WIAScanManager;
+    ///
+    /// // Either call may fail if the WIA service isn't running, but neither panics
+    /// let first = WIAScanManager::init();
+    /// let second = WIAScanManager::init();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if COM can't be initialized on this thread or the WIA service
+    /// can't be reached. COM already being initialized (`S_FALSE`) is not an
+    /// error.
+    pub fn init() -> Result<Self> {
+        Self::init_with(ComThreadingModel::MultiThreaded)
+    }
+
+    /// Like [`init`](Self::init), but with an explicit apartment model for
+    /// threads where COM hasn't been initialized yet.
+    pub fn init_with(model: ComThreadingModel) -> Result<Self> {
         unsafe {
-            CoInitializeEx(None, COINIT_MULTITHREADED).unwrap();
-        }
+            let owns_com = com::acquire(model)?;
+
+            let device_manager: IWiaDevMgr =
+                match CoCreateInstance(&WiaDevMgr, None, CLSCTX_LOCAL_SERVER) {
+                    Ok(device_manager) => device_manager,
+                    Err(e) => {
+                        if owns_com {
+                            com::release();
+                        }
+                        return Err(WiaError::from(e));
+                    }
+                };
 
-        Ok(WIAScanManager)
+            Ok(WIAScanManager {
+                device_manager: ManuallyDrop::new(device_manager),
+                owns_com,
+                _thread_bound: PhantomData,
+            })
+        }
     }
 
-    pub fn list_devices() -> std::result::Result<(), String> {
-        println!("Scanning for WIA devices...");
+    /// Connects to the WIA device manager on a thread where the host has
+    /// already initialized COM, as Electron and Tauri do on their UI thread.
+    ///
+    /// COM is neither initialized nor uninitialized by the manager, so its
+    /// lifetime stays entirely with the host, which must keep COM up until
+    /// the manager is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the WIA service can't be reached, or with `CO_E_NOTINITIALIZED`
+    /// if COM hasn't been initialized on this thread after all.
+    pub fn attach() -> Result<Self> {
+        let device_manager: IWiaDevMgr =
+            unsafe { CoCreateInstance(&WiaDevMgr, None, CLSCTX_LOCAL_SERVER)? };
+
+        Ok(WIAScanManager {
+            device_manager: ManuallyDrop::new(device_manager),
+            owns_com: false,
+            _thread_bound: PhantomData,
+        })
+    }
 
-        // Create a collection to store device IDs
-        let mut device_map: HashMap<usize, String> = HashMap::new();
+    /// Enumerates the local WIA devices without printing or prompting.
+    pub fn enumerate_devices(&self) -> Result<Vec<DeviceInfo>> {
+        self.devices()?.map(|entry| entry?.info()).collect()
+    }
 
-        // Create WIA device manager
-        unsafe {
-            let device_manager: IWiaDevMgr =
-                CoCreateInstance(&WiaDevMgr, None, CLSCTX_LOCAL_SERVER).unwrap();
+    /// Iterates over the local WIA devices lazily. Nothing is read from a
+    /// device until its [`DeviceEntry`] is asked for it, so
+    /// `devices()?.next()` stays cheap with slow or offline network
+    /// scanners installed.
+    ///
+    /// ```no_run
+    /// # let manager = wia_rs::WIAScanManager::init()?;
+    /// for entry in manager.devices()? {
+    ///     println!("{}", entry?.name()?);
+    /// }
+    /// # Ok::<(), wia_rs::WiaError>(())
+    /// ```
+    pub fn devices(&self) -> Result<Devices<'_>> {
+        let enumerator = unsafe {
+            self.device_manager.EnumDeviceInfo(WIA_DEVINFO_ENUM_LOCAL as i32)?
+        };
+        Ok(Devices::new(enumerator))
+    }
 
-            // Enumerate WIA devices
-            let enum_wia_dev: Option<IEnumWIA_DEV_INFO> = device_manager
-                .EnumDeviceInfo(WIA_DEVINFO_ENUM_LOCAL as i32)
-                .ok();
+    /// Calls `on_event` whenever a WIA device is connected or disconnected,
+    /// until the returned subscription is dropped.
+    ///
+    /// The callback runs on a thread chosen by COM. A manager initialized
+    /// with [`ComThreadingModel::SingleThreaded`] only receives events while
+    /// its thread pumps messages.
+    ///
+    /// ```no_run
+    /// use wia_rs::{DeviceEventKind, WIAScanManager};
+    ///
+    /// let manager = WIAScanManager::init()?;
+    /// let _subscription = manager.subscribe_device_events(|event| match event.kind {
+    ///     DeviceEventKind::Connected => println!("Connected: {}", event.device_id),
+    ///     DeviceEventKind::Disconnected => println!("Disconnected: {}", event.device_id),
+    ///     _ => {}
+    /// })?;
+    /// # Ok::<(), wia_rs::WiaError>(())
+    /// ```
+    pub fn subscribe_device_events(
+        &self,
+        on_event: impl FnMut(DeviceEvent) + Send + 'static,
+    ) -> Result<EventSubscription<'_>> {
+        // An empty device ID registers for every device
+        let subscription = self.subscribe(
+            &BSTR::new(),
+            &[WIA_EVENT_DEVICE_CONNECTED, WIA_EVENT_DEVICE_DISCONNECTED],
+            on_event,
+        )?;
+        log::debug!("Subscribed to device connect and disconnect events");
+        Ok(subscription)
+    }
 
-            if enum_wia_dev.is_none() {
-                println!("No WIA devices found.");
-                return Ok(());
-            }
-            let enum_wia_dev = enum_wia_dev.unwrap();
-
-            // Get device count
-            let device_count = enum_wia_dev.GetCount().map_err(|e| handle_error(e))?;
-            println!("Found {} WIA device(s)", device_count);
-
-            // Iterate through devices
-            for i in 0..device_count {
-                // Get device info
-                let mut wia_dev_info: Option<IWiaPropertyStorage> = None;
-                enum_wia_dev
-                    .Next(1, &mut wia_dev_info, std::ptr::null_mut())
-                    .map_err(|e| handle_error(e))?;
-
-                if let Some(dev_info) = wia_dev_info {
-                    // Get device ID
-                    let id_string = read_bstr_property(&dev_info, WIA_DIP_DEV_ID)?;
-                    println!("Device {}: ID = {}", i + 1, id_string);
-
-                    // Store device ID in map
-                    device_map.insert((i + 1).try_into().unwrap(), id_string);
-
-                    // Get device name
-                    let name_string = read_bstr_property(&dev_info, WIA_DIP_DEV_NAME)?;
-                    println!("      Name = {}", name_string);
-
-                    // Get device description
-                    let desc_string = read_bstr_property(&dev_info, WIA_DIP_DEV_DESC)?;
-                    println!("      Description = {}", desc_string);
-
-                    println!(); // Add empty line between devices
-                }
-            }
+    /// Calls `on_event` whenever the scan button on the given device is
+    /// pressed, until the returned subscription is dropped. Events arrive
+    /// as [`DeviceEventKind::ScanButton`](crate::DeviceEventKind::ScanButton).
+    ///
+    /// Threading works as in
+    /// [`subscribe_device_events`](Self::subscribe_device_events). Devices
+    /// without a button, or whose driver doesn't report it, never call back.
+    pub fn subscribe_scan_button(
+        &self,
+        device_id: impl Into<DeviceId>,
+        on_event: impl FnMut(DeviceEvent) + Send + 'static,
+    ) -> Result<EventSubscription<'_>> {
+        let device_id = device_id.into();
+        let subscription = self.subscribe(&device_id.to_bstr(), &[WIA_EVENT_SCAN_IMAGE], on_event)?;
+        log::debug!("Subscribed to scan button events from device: {}", device_id);
+        Ok(subscription)
+    }
 
-            // Check if any devices were found
-            if !device_map.is_empty() {
-                println!("Would you like to scan a document? (y/n)");
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input).unwrap();
-
-                if input.trim().to_lowercase() == "y" {
-                    println!("Enter the device number to use for scanning:");
-                    input.clear();
-                    std::io::stdin().read_line(&mut input).unwrap();
-
-                    if let Ok(device_num) = input.trim().parse::<usize>() {
-                        if let Some(device_id) = device_map.get(&device_num) {
-                            // Create device manager and device to check capabilities
-                            let device_manager: IWiaDevMgr =
-                                CoCreateInstance(&WiaDevMgr, None, CLSCTX_LOCAL_SERVER)
-                                    .map_err(|e| handle_error(e))?;
-                            println!("Device Manager created successfully");
-                            let device: IWiaItem = device_manager
-                                .CreateDevice(&BSTR::from(device_id))
-                                .map_err(|e| handle_error(e))?;
-                            println!("Device created successfully");
-
-                            // Find the scanner item
-                            let enum_items: IEnumWiaItem =
-                                device.EnumChildItems().map_err(|e| handle_error(e))?;
-                            let mut scanner_item: Option<IWiaItem> = None;
-                            let mut num_fetched: u32 = 0;
-                            enum_items
-                                .Next(1, &mut scanner_item, &mut num_fetched)
-                                .map_err(|e| handle_error(e))?;
-                            println!("Scanner item found successfully");
-
-                            if let Some(item) = scanner_item {
-                                let props: IWiaPropertyStorage =
-                                    item.cast().map_err(|e| handle_error(e))?;
-                                // First check device level properties for capability detection
-                                println!("Checking device level properties...");
-                                let device_props: IWiaPropertyStorage =
-                                    device.cast().map_err(|e| handle_error(e))?;
-                                let (has_feeder_device, has_flatbed_device) =
-                                    Self::check_scanner_capabilities(&device_props)?;
-
-                                // Then check item level properties
-                                println!("Checking item level properties...");
-                                let (has_feeder_item, has_flatbed_item) =
-                                    Self::check_scanner_capabilities(&props)?;
-
-                                // Combine results - if either level reports capability, consider it available
-                                let has_feeder = has_feeder_device || has_feeder_item;
-                                let has_flatbed = has_flatbed_device || has_flatbed_item;
-
-                                println!(
-                                    "Final capability detection: Feeder: {}, Flatbed: {}",
-                                    has_feeder, has_flatbed
-                                );
-
-                                let use_feeder;
-
-                                if has_feeder && has_flatbed {
-                                    println!("Select scan source:");
-                                    println!("1. Flatbed");
-                                    println!("2. Document Feeder");
-                                    input.clear();
-                                    std::io::stdin().read_line(&mut input).unwrap();
-
-                                    use_feeder = match input.trim() {
-                                        "2" => true,
-                                        _ => false, // Default to flatbed for any other input
-                                    };
-                                } else if has_feeder {
-                                    println!("Only document feeder available. Using feeder.");
-                                    use_feeder = true;
-                                } else {
-                                    println!("Only flatbed available. Using flatbed.");
-                                    use_feeder = false;
-                                }
-
-                                println!(
-                                    "Starting scan with {} source...",
-                                    if use_feeder { "feeder" } else { "flatbed" }
-                                );
-                                Self::scan_document(device_id, use_feeder)?;
-                            } else {
-                                println!("No scanner item found");
-                            }
-                        } else {
-                            println!("Invalid device number.");
-                        }
-                    } else {
-                        println!("No scanner device found");
-                    }
-                }
-            }
+    /// Registers one callback for each of `events` from the device with
+    /// `device_id`, or from every device if it is empty.
+    fn subscribe(
+        &self,
+        device_id: &BSTR,
+        events: &[GUID],
+        on_event: impl FnMut(DeviceEvent) + Send + 'static,
+    ) -> Result<EventSubscription<'_>> {
+        let callback: IWiaEventCallback = EventCallback::new(on_event).into();
+
+        let mut registrations = Vec::new();
+        for event in events {
+            let registration = unsafe {
+                self.device_manager
+                    .RegisterEventCallbackInterface(0, device_id, event, &callback)?
+            };
+            registrations.push(registration);
         }
 
-
-        Ok(())
+        Ok(EventSubscription::new(registrations))
     }
 
-    // Function to check scanner capabilities
-    fn check_scanner_capabilities(
-        props: &IWiaPropertyStorage,
-    ) -> std::result::Result<(bool, bool), String> {
-        unsafe {
-            // Check document handling capabilities
-            let mut prop_id = PROPSPEC {
-                ulKind: PRSPEC_PROPID,
-                Anonymous: PROPSPEC_0 {
-                    propid: WIA_DPS_DOCUMENT_HANDLING_CAPABILITIES,
-                },
-            };
-            let mut prop_var = PROPVARIANT::default();
+    /// Returns the only scanner on this machine.
+    ///
+    /// Cameras and other non-scanner devices are ignored. Fails with
+    /// [`WiaError::NoDeviceAvailable`] when there is no scanner and with
+    /// [`WiaError::MultipleDevices`] when there is more than one, so the
+    /// caller can fall back to letting the user pick.
+    pub fn default_device(&self) -> Result<DeviceInfo> {
+        WiaBackend::default_device(self)
+    }
 
-            let hr = props.ReadMultiple(1, &mut prop_id, &mut prop_var);
+    /// Returns the first device whose name contains `pattern`, ignoring case.
+    ///
+    /// Names survive driver reinstalls where IDs don't, which makes them the
+    /// better thing to store in configuration.
+    pub fn find_device_by_name(&self, pattern: &str) -> Result<Option<DeviceInfo>> {
+        Ok(self.find_devices_by_name(pattern)?.into_iter().next())
+    }
 
-            let mut has_feeder = false;
-            let mut has_flatbed = false;
+    /// Returns every device whose name contains `pattern`, ignoring case.
+    pub fn find_devices_by_name(&self, pattern: &str) -> Result<Vec<DeviceInfo>> {
+        WiaBackend::find_devices_by_name(self, pattern)
+    }
 
-            println!("Checking scanner capabilities...");
+    /// Like [`find_device_by_name`](Self::find_device_by_name), but fails with
+    /// [`WiaError::AmbiguousDevice`] when more than one device matches.
+    pub fn find_unique_device_by_name(&self, pattern: &str) -> Result<Option<DeviceInfo>> {
+        let mut devices = self.find_devices_by_name(pattern)?;
+        if devices.len() > 1 {
+            return Err(WiaError::AmbiguousDevice(devices));
+        }
+        Ok(devices.pop())
+    }
 
-            if hr.is_ok() {
-                if prop_var.vt() == VT_I4 {
-                    let capabilities = prop_var.Anonymous.Anonymous.Anonymous.lVal;
-                    println!("Capabilities value: {}", capabilities);
+    /// Looks up a single device by ID without enumerating the others. The
+    /// returned handle carries the device's name and description.
+    ///
+    /// Fails with [`WiaError::DeviceNotFound`] if no such device is
+    /// installed.
+    pub fn get_device(&self, device_id: impl Into<DeviceId>) -> Result<WiaDevice<'_>> {
+        self.open_device_by_id(device_id)
+    }
 
-                    // Debug specific capability flags
-                    println!("FEEDER value: {}", FEEDER as i32);
-                    println!("FLATBED value: {}", FLATBED as i32);
+    /// Opens a [`ScanSession`] that keeps the device connected for a run of
+    /// scans.
+    pub fn session(&self, device_id: impl Into<DeviceId>) -> Result<ScanSession<'_>> {
+        ScanSession::open(self, device_id)
+    }
 
-                    has_feeder = (capabilities & (FEEDER as i32)) != 0;
-                    has_flatbed = (capabilities & (FLATBED as i32)) != 0;
+    /// Opens a handle to the device with the given ID. The handle can be
+    /// reused for any number of scans without reconnecting.
+    pub fn open_device(&self, device_id: impl Into<DeviceId>) -> Result<WiaDevice<'_>> {
+        self.open_device_by_id(device_id)
+    }
 
-                    println!("Has feeder: {}, Has flatbed: {}", has_feeder, has_flatbed);
-                } else {
-                    // println!("Unexpected property type: {} (expected VT_I4)", prop_var.vt.0);
-                }
+    /// Reconnects to a device from a previously stored ID without
+    /// enumerating. Returns [`WiaError::DeviceNotFound`] if the device has
+    /// been unplugged or its driver uninstalled since the ID was saved.
+    pub fn open_device_by_id(&self, device_id: impl Into<DeviceId>) -> Result<WiaDevice<'_>> {
+        WiaDevice::connect(&self.device_manager, &device_id.into())
+    }
 
-                PropVariantClear(&mut prop_var).map_err(|e| handle_error(e))?;
-            } else {
-                println!("Failed to read document handling capabilities: {:?}", hr);
+    /// Like [`open_device`](Self::open_device), but fails with
+    /// [`WiaError::Timeout`] if the device doesn't answer within `timeout`.
+    ///
+    /// Useful for network scanners, where connecting to one that is powered
+    /// off can block for half a minute.
+    pub fn open_device_with_timeout(
+        &self,
+        device_id: impl Into<DeviceId>,
+        timeout: Duration,
+    ) -> Result<WiaDevice<'_>> {
+        WiaDevice::connect_with_timeout(&device_id.into(), timeout)
+    }
 
-                // Try to read device properties directly
-                let mut prop_status = PROPSPEC {
-                    ulKind: PRSPEC_PROPID,
-                    Anonymous: PROPSPEC_0 {
-                        propid: WIA_DPS_DOCUMENT_HANDLING_STATUS,
-                    },
-                };
-                let mut status_var = PROPVARIANT::default();
-                if props
-                    .ReadMultiple(1, &mut prop_status, &mut status_var)
-                    .is_ok()
-                {
-                    if status_var.vt() == VT_I4 {
-                        let status = status_var.Anonymous.Anonymous.Anonymous.lVal;
-                        println!("Document handling status: {}", status);
-                        has_feeder = (status & (FEEDER as i32)) != 0;
-                        has_flatbed = true; // Assume flatbed is available
-                    }
-                    PropVariantClear(&mut status_var).map_err(|e| handle_error(e))?;
-                }
-            }
+    /// Connects to the device and detects its document handling
+    /// capabilities. See [`WiaDevice::capabilities`].
+    pub fn scanner_capabilities(&self, device_id: impl Into<DeviceId>) -> Result<Capabilities> {
+        self.open_device(device_id)?.capabilities()
+    }
 
-            // Default to assuming both are available if detection fails
-            if !has_feeder && !has_flatbed {
-                println!("Could not detect capabilities, assuming both are available");
-                has_feeder = true;
-                has_flatbed = true;
-            }
+    /// Connects to the device and lists its child items. Pass one's name
+    /// to [`ScanOptions::item`] to scan from it.
+    pub fn list_items(&self, device_id: impl Into<DeviceId>) -> Result<Vec<WiaItemInfo>> {
+        self.open_device(device_id)?.items()
+    }
 
-            Ok((has_feeder, has_flatbed))
-        }
+    /// Connects to the device and scans a single document from the given
+    /// source.
+    pub fn scan_document(
+        &self,
+        device_id: impl Into<DeviceId>,
+        source: ScanSource,
+        output: impl AsRef<Path>,
+    ) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        log::debug!("Scanning document from device: {}", device_id);
+        self.open_device(device_id)?.scan(source, output)
     }
 
-    pub fn scan_document(device_id: &str, use_feeder: bool) -> std::result::Result<(), String> {
-        println!("Scanning document from device: {}", device_id);
-        unsafe {
-            // Create WIA device manager
-            let device_manager: IWiaDevMgr =
-                CoCreateInstance(&WiaDevMgr, None, CLSCTX_LOCAL_SERVER).map_err(|e| handle_error(e))?;
-            println!("WIA Device Manager created.");
-
-            // Connect to the specific device
-            println!("Connecting to device: {}", device_id);
-            let device: IWiaItem = device_manager
-                .CreateDevice(&BSTR::from(device_id))
-                .map_err(|e| handle_error(e))?;
-            println!("Connected to device: {}", device_id);
-
-            // Set document handling on the root device
-            let device_props: IWiaPropertyStorage = device.cast().map_err(|e| handle_error(e))?;
-            let mut prop_id = PROPSPEC {
-                ulKind: PRSPEC_PROPID,
-                Anonymous: PROPSPEC_0 {
-                    propid: WIA_IPS_DOCUMENT_HANDLING_SELECT,
-                },
-            };
-            let mut prop_var = PROPVARIANT::default();
-            let handling_value = if use_feeder { FEEDER } else { FLATBED };
-            println!(
-                "Setting document handling select to: {} ({})",
-                if use_feeder { "FEEDER" } else { "FLATBED" },
-                handling_value as i32
-            );
-            (*prop_var.Anonymous.Anonymous).Anonymous.lVal = handling_value as i32;
-            (*prop_var.Anonymous.Anonymous).vt = VT_I4;
-            let hr = device_props.WriteMultiple(1, &mut prop_id, &mut prop_var, 1);
-            if hr.is_err() {
-                println!("Warning: Failed to set document handling mode: {:?}", hr);
-                // Try to continue anyway
-            }
+    /// Scans a document without blocking the caller, for use from async
+    /// runtimes.
+    ///
+    /// The scan runs on a dedicated thread with its own single-threaded
+    /// apartment and manager, so this doesn't take `self`: the returned
+    /// future is `Send` and can be awaited anywhere. Requires the `async`
+    /// feature.
+    #[cfg(feature = "async")]
+    pub fn scan_document_async(
+        device_id: impl Into<DeviceId>,
+        source: ScanSource,
+        output: impl AsRef<Path>,
+    ) -> impl Future<Output = Result<ScanResult>> + Send + 'static {
+        let device_id = device_id.into();
+        let output = output.as_ref().to_path_buf();
+        future::spawn("wia-scan", move || {
+            let manager = Self::init_with(ComThreadingModel::SingleThreaded)?;
+            manager.scan_document(device_id, source, output)
+        })
+    }
 
-            // Re-enumerate to get the correct scanning item
-            let enum_items: IEnumWiaItem = device.EnumChildItems().map_err(|e| handle_error(e))?;
-            let mut scan_item: Option<IWiaItem> = None;
-            let mut num_fetched: u32 = 0;
-            enum_items
-                .Next(1, &mut scan_item, &mut num_fetched)
-                .map_err(|e| handle_error(e))?;
-            if scan_item.is_none() {
-                println!("No scan item found after setting handling mode.");
-                return Ok(());
+    /// Like [`scan_document`](Self::scan_document), but fails with
+    /// [`WiaError::Timeout`] if the scan doesn't finish within `timeout`.
+    ///
+    /// The scan runs on a worker thread with its own COM initialization and
+    /// manager. On timeout the transfer is asked to cancel through its data
+    /// callback; a driver stuck without calling back is left to finish on
+    /// the worker, which cleans up after itself.
+    pub fn scan_document_with_timeout(
+        &self,
+        device_id: impl Into<DeviceId>,
+        source: ScanSource,
+        output: impl AsRef<Path>,
+        timeout: Duration,
+    ) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        let options = ScanOptions::new().source(source).output(output.as_ref());
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (result_tx, result_rx) = mpsc::channel();
+
+        let worker_cancelled = Arc::clone(&cancelled);
+        let worker_id = device_id.clone();
+        let worker = std::thread::Builder::new()
+            .name("wia-scan".to_string())
+            .spawn(move || {
+                let result = Self::init().and_then(|manager| {
+                    let callback: IWiaDataCallback = CancelCallback::new(worker_cancelled).into();
+                    manager
+                        .open_device(worker_id)?
+                        .scan_with_callback(&options, Some(&callback))
+                });
+                // Nobody is listening any more if the caller timed out
+                let _ = result_tx.send(result);
+            })
+            .map_err(|e| WiaError::Io(format!("Failed to start the scan thread: {}", e)))?;
+
+        match result_rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                log::warn!("Timed out scanning from device: {}", device_id);
+                cancelled.store(true, Ordering::Relaxed);
+                Err(WiaError::Timeout)
             }
-            let scan_item = scan_item.unwrap();
-
-            // Create a temporary file path for the output
-            let output_path = "scanned_document.pdf";
-            let wide_path: Vec<u16> = output_path
-                .encode_utf16()
-                .chain(std::iter::once(0))
-                .collect();
-
-            // Set up the transfer medium
-            let mut stgm = STGMEDIUM::default();
-            stgm.tymed = TYMED_FILE.0 as u32;
-            stgm.u.lpszFileName = PWSTR(wide_path.as_ptr() as *mut u16);
-
-            // Get the IWiaDataTransfer from the scan item
-            let data_transfer: IWiaDataTransfer = scan_item.cast().map_err(|e| handle_error(e))?;
-
-            println!("Saving document to {}", output_path);
-            data_transfer
-                .idtGetData(&mut stgm, None)
-                .map_err(|e| handle_error(e))?;
-
-            println!("Scan complete! Document saved as: {}", output_path);
-            Ok(())
+            // The worker always sends unless it panicked
+            Err(RecvTimeoutError::Disconnected) => match worker.join() {
+                Err(panic) => std::panic::resume_unwind(panic),
+                Ok(()) => Err(WiaError::Timeout),
+            },
         }
     }
 
+    /// Like [`open_device`](Self::open_device), but retries transient
+    /// failures according to `policy`.
+    pub fn open_device_with_retry(
+        &self,
+        device_id: impl Into<DeviceId>,
+        policy: &RetryPolicy,
+    ) -> Result<WiaDevice<'_>> {
+        let device_id = device_id.into();
+        let (device, attempt) = with_retry(policy, || self.open_device(&device_id))?;
+        log::debug!("Connected to {} on attempt {}", device_id, attempt);
+        Ok(device)
+    }
+
+    /// Like [`scan_document`](Self::scan_document), but retries transient
+    /// failures according to `policy`. The attempt that succeeded is
+    /// reported in [`ScanResult::attempt`].
+    pub fn scan_document_with_retry(
+        &self,
+        device_id: impl Into<DeviceId>,
+        source: ScanSource,
+        output: impl AsRef<Path>,
+        policy: &RetryPolicy,
+    ) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        let output = output.as_ref();
+        let (mut result, attempt) =
+            with_retry(policy, || self.scan_document(&device_id, source, output))?;
+        result.attempt = attempt;
+        Ok(result)
+    }
+
+    /// Connects to the device and scans a single document with the given
+    /// options.
+    pub fn scan_with_options(
+        &self,
+        device_id: impl Into<DeviceId>,
+        options: &ScanOptions,
+    ) -> Result<ScanResult> {
+        // Don't bother connecting if the options can never work
+        options.validate()?;
+        let device_id = device_id.into();
+        log::debug!("Scanning document from device: {}", device_id);
+        self.open_device(device_id)?.scan_with_options(options)
+    }
+
+    /// Connects to the device and scans a document, reporting progress as it
+    /// goes. See [`WiaDevice::scan_with_progress`].
+    pub fn scan_document_with_progress(
+        &self,
+        device_id: impl Into<DeviceId>,
+        source: ScanSource,
+        output: impl AsRef<Path>,
+        progress: impl FnMut(u32, &[u8]) + Send + 'static,
+    ) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        log::debug!("Scanning document from device: {}", device_id);
+        self.open_device(device_id)?.scan_with_progress(source, output, progress)
+    }
+
+    /// Connects to the device and scans a document into memory. See
+    /// [`WiaDevice::scan_to_memory`].
+    pub fn scan_to_memory(
+        &self,
+        device_id: impl Into<DeviceId>,
+        source: ScanSource,
+        format: ScanFormat,
+    ) -> Result<Vec<u8>> {
+        let device_id = device_id.into();
+        log::debug!("Scanning document to memory from device: {}", device_id);
+        self.open_device(device_id)?.scan_to_memory(source, format)
+    }
+
+    /// Connects to the device and scans a low-resolution PNG preview. See
+    /// [`WiaDevice::preview`].
+    pub fn preview(&self, device_id: impl Into<DeviceId>, source: ScanSource) -> Result<Vec<u8>> {
+        let device_id = device_id.into();
+        log::debug!("Scanning preview from device: {}", device_id);
+        self.open_device(device_id)?.preview(source)
+    }
+
+    /// Connects to the device and scans every page in its feeder into
+    /// `output_dir`. See [`WiaDevice::scan_all_pages`].
+    pub fn scan_all_pages(
+        &self,
+        device_id: impl Into<DeviceId>,
+        output_dir: impl AsRef<Path>,
+        format: ScanFormat,
+    ) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        log::debug!("Scanning all pages from device: {}", device_id);
+        self.open_device(device_id)?.scan_all_pages(output_dir, format)
+    }
+
+    /// Connects to the device and scans both sides of every sheet in its
+    /// feeder into `output_dir`. See [`WiaDevice::scan_all_pages_duplex`].
+    pub fn scan_all_pages_duplex(
+        &self,
+        device_id: impl Into<DeviceId>,
+        output_dir: impl AsRef<Path>,
+        format: ScanFormat,
+    ) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        log::debug!("Scanning all pages, both sides, from device: {}", device_id);
+        self.open_device(device_id)?.scan_all_pages_duplex(output_dir, format)
+    }
+
+    /// Connects to the device and scans every page in its feeder into one
+    /// multi-page file. See [`WiaDevice::scan_to_document`].
+    pub fn scan_to_document(
+        &self,
+        device_id: impl Into<DeviceId>,
+        output: impl AsRef<Path>,
+        format: DocumentFormat,
+    ) -> Result<ScanResult> {
+        let device_id = device_id.into();
+        log::debug!("Scanning a multi-page document from device: {}", device_id);
+        self.open_device(device_id)?.scan_to_document(output, format)
+    }
 }
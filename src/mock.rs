@@ -0,0 +1,244 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use windows::Win32::Devices::ImageAcquisition::{WIA_PROP_LIST, WIA_PROP_RANGE};
+use windows::Win32::Foundation::{E_INVALIDARG, S_OK};
+use windows::core::{GUID, HRESULT};
+
+use crate::backend::{BackendDevice, ItemLevel, WiaBackend};
+use crate::util::WiaValue;
+use crate::{DeviceId, DeviceInfo, DeviceType, Result, WiaError};
+
+/// A [`WiaBackend`] with canned devices, for running scanning code without
+/// a scanner or the WIA service.
+///
+/// ```
+/// use wia_rs::{BackendDevice, ItemLevel, MockBackend, MockDevice, ScanOptions, WiaBackend};
+/// use wia_rs::WiaValue;
+///
+/// const WIA_DPS_DOCUMENT_HANDLING_STATUS: u32 = 3087;
+/// const FEED_READY: i32 = 1;
+///
+/// let backend = MockBackend::new().device(
+///     MockDevice::new("{6BDD1FC6-810F-11D0-BEC7-08002BE2092F}\\0000", "Test Scanner")
+///         .property(ItemLevel::Root, WIA_DPS_DOCUMENT_HANDLING_STATUS, WiaValue::I4(FEED_READY))
+///         .page(b"BM first".to_vec())
+///         .page(b"BM second".to_vec()),
+/// );
+///
+/// let info = backend.default_device()?;
+/// let device = backend.create_device(&info.id)?;
+/// assert!(device.feeder_status()?.paper_loaded);
+/// assert_eq!(device.transfer(&ScanOptions::new())?, b"BM first");
+/// assert_eq!(device.transfer(&ScanOptions::new())?, b"BM second");
+/// assert!(device.transfer(&ScanOptions::new()).is_err());
+/// # Ok::<(), wia_rs::WiaError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    devices: Vec<MockDevice>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a device, listed in the order added.
+    pub fn device(mut self, device: MockDevice) -> Self {
+        self.devices.push(device);
+        self
+    }
+}
+
+impl WiaBackend for MockBackend {
+    type Device<'a> = &'a MockDevice;
+
+    fn enumerate_devices(&self) -> Result<Vec<DeviceInfo>> {
+        Ok(self.devices.iter().map(|device| device.info.clone()).collect())
+    }
+
+    fn create_device(&self, device_id: &DeviceId) -> Result<&MockDevice> {
+        self.devices
+            .iter()
+            .find(|device| device.info.id == *device_id)
+            .ok_or_else(|| WiaError::DeviceNotFound(device_id.clone()))
+    }
+}
+
+/// A device of a [`MockBackend`]. Properties that were never set read as
+/// [`WiaValue::Empty`], like ones a driver doesn't expose.
+///
+/// Every write is recorded, so tests can check what a set of
+/// [`ScanOptions`](crate::ScanOptions) asks the driver for:
+///
+/// ```
+/// use wia_rs::{BackendDevice, ItemLevel, MockDevice, ScanOptions, WiaValue};
+///
+/// const WIA_IPA_TYMED: u32 = 4108;
+/// const TYMED_CALLBACK: i32 = 128;
+/// const WIA_IPS_XRES: u32 = 6147;
+/// const WIA_IPS_YRES: u32 = 6148;
+///
+/// let device = MockDevice::new("{6BDD1FC6-810F-11D0-BEC7-08002BE2092F}\\0000", "Test Scanner")
+///     .range(ItemLevel::Scan, WIA_IPS_XRES, 75, 1200, 1)
+///     .page(b"BM".to_vec());
+///
+/// device.transfer(&ScanOptions::new().dpi(300))?;
+/// assert_eq!(
+///     device.writes(),
+///     [
+///         (ItemLevel::Scan, WIA_IPA_TYMED, WiaValue::I4(TYMED_CALLBACK)),
+///         (ItemLevel::Scan, WIA_IPS_XRES, WiaValue::I4(300)),
+///         (ItemLevel::Scan, WIA_IPS_YRES, WiaValue::I4(300)),
+///     ]
+/// );
+/// assert!(device.transfer(&ScanOptions::new().dpi(2400)).is_err());
+/// # Ok::<(), wia_rs::WiaError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    info: DeviceInfo,
+    properties: RefCell<HashMap<(ItemLevel, u32), WiaValue>>,
+    attributes: HashMap<(ItemLevel, u32), (u32, Vec<i32>)>,
+    read_only: HashSet<(ItemLevel, u32)>,
+    rejected: Vec<(ItemLevel, u32, WiaValue)>,
+    writes: RefCell<Vec<(ItemLevel, u32, WiaValue)>>,
+    pages: RefCell<VecDeque<Vec<u8>>>,
+}
+
+impl MockDevice {
+    /// A scanner with the given ID and name and no pages to transfer.
+    pub fn new(id: impl Into<DeviceId>, name: &str) -> Self {
+        MockDevice {
+            info: DeviceInfo {
+                id: id.into(),
+                name: name.to_string(),
+                description: String::new(),
+                device_type: DeviceType::Scanner,
+                vendor: None,
+                server_name: None,
+                port_name: None,
+                driver_version: None,
+            },
+            properties: RefCell::new(HashMap::new()),
+            attributes: HashMap::new(),
+            read_only: HashSet::new(),
+            rejected: Vec::new(),
+            writes: RefCell::new(Vec::new()),
+            pages: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    pub fn device_type(mut self, device_type: DeviceType) -> Self {
+        self.info.device_type = device_type;
+        self
+    }
+
+    pub fn property(self, level: ItemLevel, prop_id: u32, value: WiaValue) -> Self {
+        self.properties.borrow_mut().insert((level, prop_id), value);
+        self
+    }
+
+    /// Reports `min..=max` in steps of `step` as a property's valid values,
+    /// which the crate checks settings against before writing them.
+    pub fn range(mut self, level: ItemLevel, prop_id: u32, min: i32, max: i32, step: i32) -> Self {
+        let nominal = min;
+        self.attributes
+            .insert((level, prop_id), (WIA_PROP_RANGE, vec![min, nominal, max, step]));
+        self
+    }
+
+    /// Reports `values` as the only valid values of a property.
+    pub fn list(mut self, level: ItemLevel, prop_id: u32, values: &[i32]) -> Self {
+        let nominal = values.first().copied().unwrap_or_default();
+        let mut layout = vec![values.len() as i32, nominal];
+        layout.extend_from_slice(values);
+        self.attributes.insert((level, prop_id), (WIA_PROP_LIST, layout));
+        self
+    }
+
+    /// Makes writes to a property fail, like a driver that doesn't accept
+    /// any value for it.
+    pub fn read_only(mut self, level: ItemLevel, prop_id: u32) -> Self {
+        self.read_only.insert((level, prop_id));
+        self
+    }
+
+    /// Makes writes of `value` to a property fail, like a driver whose
+    /// valid values leave it out.
+    pub fn rejects(mut self, level: ItemLevel, prop_id: u32, value: WiaValue) -> Self {
+        self.rejected.push((level, prop_id, value));
+        self
+    }
+
+    /// Queues an image for the next transfer. Each transfer takes one, and
+    /// once they run out transfers fail with [`WiaError::PaperEmpty`].
+    pub fn page(self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.pages.borrow_mut().push_back(bytes.into());
+        self
+    }
+
+    pub fn info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// Every write the device received, in order.
+    pub fn writes(&self) -> Vec<(ItemLevel, u32, WiaValue)> {
+        self.writes.borrow().clone()
+    }
+
+    /// The current value of a property, including anything written to it.
+    pub fn property_value(&self, level: ItemLevel, prop_id: u32) -> WiaValue {
+        self.properties
+            .borrow()
+            .get(&(level, prop_id))
+            .cloned()
+            .unwrap_or(WiaValue::Empty)
+    }
+}
+
+impl BackendDevice for MockDevice {
+    fn read_property(&self, level: ItemLevel, prop_id: u32) -> Result<WiaValue> {
+        Ok(self.property_value(level, prop_id))
+    }
+
+    fn write_property(&self, level: ItemLevel, prop_id: u32, value: i32) -> Result<()> {
+        self.store(level, prop_id, WiaValue::I4(value))
+    }
+
+    fn write_guid_property(&self, level: ItemLevel, prop_id: u32, value: GUID) -> Result<()> {
+        self.store(level, prop_id, WiaValue::Guid(value))
+    }
+
+    fn property_attributes(&self, level: ItemLevel, prop_id: u32) -> Result<(u32, Vec<i32>)> {
+        self.attributes
+            .get(&(level, prop_id))
+            .cloned()
+            .ok_or_else(|| WiaError::from(windows::core::Error::from(E_INVALIDARG)))
+    }
+
+    fn transfer_image(&self) -> Result<Vec<u8>> {
+        self.pages.borrow_mut().pop_front().ok_or(WiaError::PaperEmpty)
+    }
+
+    fn transfer_file(&self, path: &Path) -> Result<HRESULT> {
+        let page = self.transfer_image()?;
+        std::fs::write(path, page).map_err(|e| WiaError::Io(e.to_string()))?;
+        Ok(S_OK)
+    }
+}
+
+impl MockDevice {
+    fn store(&self, level: ItemLevel, prop_id: u32, value: WiaValue) -> Result<()> {
+        if self.read_only.contains(&(level, prop_id))
+            || self.rejected.contains(&(level, prop_id, value.clone()))
+        {
+            return Err(WiaError::from(windows::core::Error::from(E_INVALIDARG)));
+        }
+        self.writes.borrow_mut().push((level, prop_id, value.clone()));
+        self.properties.borrow_mut().insert((level, prop_id), value);
+        Ok(())
+    }
+}
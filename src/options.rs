@@ -0,0 +1,921 @@
+use std::path::PathBuf;
+
+use windows::{Win32::Devices::ImageAcquisition::*, core::GUID};
+
+use crate::{Result, WiaError};
+
+/// Where the scanner should take the document from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScanSource {
+    Flatbed,
+    Feeder,
+    /// The feeder, scanning both sides of each sheet.
+    FeederDuplex,
+    /// The feeder if it has paper loaded, the flatbed otherwise.
+    Auto,
+    /// The flatbed's transparency adapter, for film and slides.
+    Transparency,
+}
+
+impl ScanSource {
+    /// The `WIA_DPS_DOCUMENT_HANDLING_SELECT` flags for this source, or
+    /// `None` for [`ScanSource::Auto`], which has to be resolved against the
+    /// device first.
+    pub(crate) fn handling_select(self) -> Option<u32> {
+        match self {
+            // The adapter is a light source over the flatbed, selected
+            // separately through WIA_DPS_TRANSPARENCY_SELECT
+            ScanSource::Flatbed | ScanSource::Transparency => Some(FLATBED),
+            ScanSource::Feeder => Some(FEEDER),
+            ScanSource::FeederDuplex => Some(FEEDER | DUPLEX),
+            ScanSource::Auto => None,
+        }
+    }
+}
+
+/// The data type the scanner should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ColorMode {
+    Color,
+    Grayscale,
+    BlackAndWhite,
+}
+
+impl ColorMode {
+    pub(crate) fn data_type(self) -> u32 {
+        match self {
+            ColorMode::Color => WIA_DATA_COLOR,
+            ColorMode::Grayscale => WIA_DATA_GRAYSCALE,
+            ColorMode::BlackAndWhite => WIA_DATA_THRESHOLD,
+        }
+    }
+
+    pub(crate) fn intent(self) -> u32 {
+        match self {
+            ColorMode::Color => WIA_INTENT_IMAGE_TYPE_COLOR,
+            ColorMode::Grayscale => WIA_INTENT_IMAGE_TYPE_GRAYSCALE,
+            ColorMode::BlackAndWhite => WIA_INTENT_IMAGE_TYPE_TEXT,
+        }
+    }
+
+    /// Maps a `WIA_IPA_DATATYPE` value back to a mode, if it is one of ours.
+    pub(crate) fn from_data_type(data_type: i32) -> Option<Self> {
+        match data_type as u32 {
+            WIA_DATA_COLOR => Some(ColorMode::Color),
+            WIA_DATA_GRAYSCALE => Some(ColorMode::Grayscale),
+            WIA_DATA_THRESHOLD => Some(ColorMode::BlackAndWhite),
+            _ => None,
+        }
+    }
+}
+
+/// Processing hints ORed into `WIA_IPS_CUR_INTENT` next to the color mode.
+/// Drivers use them to choose their internal processing path, some to
+/// decide whether to compress in hardware.
+///
+/// ```
+/// use wia_rs::IntentFlags;
+///
+/// let flags = IntentFlags::MINIMIZE_SIZE;
+/// assert!(flags.contains(IntentFlags::MINIMIZE_SIZE));
+/// assert!(!flags.contains(IntentFlags::MAXIMIZE_QUALITY));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IntentFlags(u32);
+
+impl IntentFlags {
+    pub const NONE: Self = IntentFlags(0);
+    pub const MINIMIZE_SIZE: Self = IntentFlags(WIA_INTENT_MINIMIZE_SIZE);
+    pub const MAXIMIZE_QUALITY: Self = IntentFlags(WIA_INTENT_MAXIMIZE_QUALITY);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub(crate) fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl std::ops::BitOr for IntentFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        IntentFlags(self.0 | other.0)
+    }
+}
+
+/// The image format the driver should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScanFormat {
+    Bmp,
+    Jpeg,
+    Png,
+    Tiff,
+    /// PDF/A straight from the driver (`WiaImgFmt_PDFA`), the only PDF
+    /// flavor WIA defines a format for. Drivers that only produce plain
+    /// PDF reject it, so with the `pdf` feature those scan a BMP instead,
+    /// which is wrapped into a single-page PDF at the output path, without
+    /// any compression that was asked for.
+    Pdf,
+}
+
+impl ScanFormat {
+    pub(crate) fn guid(self) -> GUID {
+        match self {
+            ScanFormat::Bmp => WiaImgFmt_BMP,
+            ScanFormat::Jpeg => WiaImgFmt_JPEG,
+            ScanFormat::Png => WiaImgFmt_PNG,
+            ScanFormat::Tiff => WiaImgFmt_TIFF,
+            ScanFormat::Pdf => WiaImgFmt_PDFA,
+        }
+    }
+
+    /// The conventional file extension for this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ScanFormat::Bmp => "bmp",
+            ScanFormat::Jpeg => "jpg",
+            ScanFormat::Png => "png",
+            ScanFormat::Tiff => "tif",
+            ScanFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// How the driver compresses the image data, through `WIA_IPA_COMPRESSION`.
+/// Each file format only carries some of them, see
+/// [`ScanOptions::compression`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Compression {
+    None,
+    /// Run-length encoding of 4-bit BMP images.
+    Rle4,
+    /// Run-length encoding of 8-bit BMP images.
+    Rle8,
+    /// CCITT Group 3 fax compression, for black and white TIFF.
+    G3,
+    /// CCITT Group 4 fax compression, for black and white TIFF.
+    G4,
+    Jpeg,
+    Png,
+}
+
+impl Compression {
+    pub(crate) fn wia_value(self) -> u32 {
+        match self {
+            Compression::None => WIA_COMPRESSION_NONE,
+            Compression::Rle4 => WIA_COMPRESSION_BI_RLE4,
+            Compression::Rle8 => WIA_COMPRESSION_BI_RLE8,
+            Compression::G3 => WIA_COMPRESSION_G3,
+            Compression::G4 => WIA_COMPRESSION_G4,
+            Compression::Jpeg => WIA_COMPRESSION_JPEG,
+            Compression::Png => WIA_COMPRESSION_PNG,
+        }
+    }
+
+    /// Whether files of `format` can hold image data compressed this way.
+    fn fits(self, format: ScanFormat) -> bool {
+        match format {
+            ScanFormat::Bmp => {
+                matches!(self, Compression::None | Compression::Rle4 | Compression::Rle8)
+            }
+            ScanFormat::Jpeg => self == Compression::Jpeg,
+            ScanFormat::Png => matches!(self, Compression::None | Compression::Png),
+            ScanFormat::Tiff => matches!(
+                self,
+                Compression::None | Compression::G3 | Compression::G4 | Compression::Jpeg
+            ),
+            ScanFormat::Pdf => matches!(self, Compression::None | Compression::Jpeg),
+        }
+    }
+}
+
+/// The size of paper being scanned, mostly for the document feeder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PageSize {
+    A3,
+    A4,
+    A5,
+    Letter,
+    Legal,
+    /// Lets the device detect the size, on devices that can.
+    Auto,
+    /// Width and height in thousandths of an inch. See
+    /// [`custom_mm`](Self::custom_mm) for sizes in millimeters.
+    Custom { width: u32, height: u32 },
+}
+
+impl PageSize {
+    /// A custom size given in millimeters.
+    pub fn custom_mm(width_mm: f32, height_mm: f32) -> Self {
+        let to_mils = |mm: f32| (mm.max(0.0) * 1000.0 / 25.4).round() as u32;
+        PageSize::Custom {
+            width: to_mils(width_mm),
+            height: to_mils(height_mm),
+        }
+    }
+
+    pub(crate) fn wia_page(self) -> u32 {
+        match self {
+            PageSize::A3 => WIA_PAGE_ISO_A3,
+            PageSize::A4 => WIA_PAGE_A4,
+            PageSize::A5 => WIA_PAGE_ISO_A5,
+            PageSize::Letter => WIA_PAGE_LETTER,
+            PageSize::Legal => WIA_PAGE_USLEGAL,
+            PageSize::Auto => WIA_PAGE_AUTO,
+            PageSize::Custom { .. } => WIA_PAGE_CUSTOM,
+        }
+    }
+
+    /// Width and height in thousandths of an inch, `None` for
+    /// [`PageSize::Auto`].
+    pub(crate) fn dimensions(self) -> Option<(u32, u32)> {
+        match self {
+            PageSize::A3 => Some((11693, 16535)),
+            PageSize::A4 => Some((8268, 11693)),
+            PageSize::A5 => Some((5827, 8268)),
+            PageSize::Letter => Some((8500, 11000)),
+            PageSize::Legal => Some((8500, 14000)),
+            PageSize::Auto => None,
+            PageSize::Custom { width, height } => Some((width, height)),
+        }
+    }
+}
+
+/// How far to rotate the scanned image, clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rotation {
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    pub(crate) fn wia_value(self) -> u32 {
+        match self {
+            Rotation::None => PORTRAIT,
+            Rotation::Rotate90 => LANDSCAPE,
+            Rotation::Rotate180 => ROT180,
+            Rotation::Rotate270 => ROT270,
+        }
+    }
+}
+
+/// Which way pages are loaded in the feeder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+impl Orientation {
+    pub(crate) fn wia_value(self) -> u32 {
+        match self {
+            Orientation::Portrait => PORTRAIT,
+            Orientation::Landscape => LANDSCAPE,
+        }
+    }
+}
+
+/// The property a [`Rotation`] or [`Orientation`] was applied through.
+/// Drivers often support only one of the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationProperty {
+    /// `WIA_IPS_ROTATION`
+    Rotation,
+    /// `WIA_IPS_ORIENTATION`
+    Orientation,
+}
+
+/// Whether the driver crops the scan to what's on the bed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AutoCrop {
+    /// The whole scan area is returned.
+    Disabled,
+    /// The scan is cropped to the one document or photo detected.
+    SingleRegion,
+    /// Every document or photo detected is returned as its own image, see
+    /// [`ScanResult::regions`].
+    MultiRegion,
+}
+
+impl AutoCrop {
+    pub(crate) fn wia_value(self) -> u32 {
+        match self {
+            AutoCrop::Disabled => WIA_AUTO_CROP_DISABLED,
+            AutoCrop::SingleRegion => WIA_AUTO_CROP_SINGLE,
+            AutoCrop::MultiRegion => WIA_AUTO_CROP_MULTI,
+        }
+    }
+}
+
+/// A rectangle of the scan bed, in pixels at the scan's resolution.
+///
+/// ```no_run
+/// use wia_rs::{ScanOptions, ScanRegion};
+///
+/// // The top half of a letter-size bed
+/// let options = ScanOptions::new().dpi(300).region(ScanRegion {
+///     x: 0,
+///     y: 0,
+///     width: 2550,
+///     height: 1650,
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A brightness or contrast setting, either in the driver's own units or
+/// scaled from -1.0..=1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) enum Level {
+    Absolute(i32),
+    Normalized(f32),
+}
+
+impl Level {
+    /// Describes how the value written differs from the one asked for, if
+    /// it had to be clamped.
+    pub(crate) fn clamp_warning(self, name: &str, written: i32) -> Option<String> {
+        match self {
+            Level::Absolute(value) if value != written => Some(format!(
+                "{} {} is outside the device's range, using {}",
+                name, value, written
+            )),
+            Level::Normalized(value) if !(-1.0..=1.0).contains(&value) => Some(format!(
+                "{} {} is outside -1.0..=1.0, using {}",
+                name, value, written
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// A file format that holds several pages, for
+/// [`WiaDevice::scan_to_document`](crate::WiaDevice::scan_to_document).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentFormat {
+    Tiff,
+    Pdf,
+}
+
+impl DocumentFormat {
+    /// The conventional file extension for this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            DocumentFormat::Tiff => "tif",
+            DocumentFormat::Pdf => "pdf",
+        }
+    }
+}
+
+const DEFAULT_OUTPUT_STEM: &str = "scanned_document";
+
+/// Settings for a single scan. Anything left unset keeps the driver's
+/// current value.
+///
+/// ```no_run
+/// use wia_rs::{ScanFormat, ScanOptions, ScanSource};
+///
+/// let options = ScanOptions::new()
+///     .source(ScanSource::Feeder)
+///     .dpi(300)
+///     .format(ScanFormat::Bmp)
+///     .output("C:\\scans\\out.bmp");
+/// ```
+///
+/// Options are plain values, so presets can be built once and reused:
+///
+/// ```no_run
+/// use wia_rs::{ColorMode, ScanFormat, ScanSettings, ScanSource, WIAScanManager};
+///
+/// let preset = ScanSettings::new()
+///     .source(ScanSource::Feeder)
+///     .resolution(300)
+///     .color(ColorMode::Color)
+///     .format(ScanFormat::Jpeg)
+///     .build()?;
+///
+/// let manager = WIAScanManager::init()?;
+/// let device = manager.default_device()?;
+/// manager.scan_with_options(&device.id, &preset)?;
+/// # Ok::<(), wia_rs::WiaError>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ScanOptions {
+    pub(crate) source: Option<ScanSource>,
+    pub(crate) item: Option<String>,
+    pub(crate) x_dpi: Option<u32>,
+    pub(crate) y_dpi: Option<u32>,
+    pub(crate) color_mode: Option<ColorMode>,
+    pub(crate) intent: IntentFlags,
+    pub(crate) bit_depth: Option<u8>,
+    pub(crate) threshold: Option<u8>,
+    pub(crate) auto_exposure: Option<bool>,
+    pub(crate) brightness: Option<Level>,
+    pub(crate) contrast: Option<Level>,
+    pub(crate) page_size: Option<PageSize>,
+    pub(crate) region: Option<ScanRegion>,
+    pub(crate) rotation: Option<Rotation>,
+    pub(crate) orientation: Option<Orientation>,
+    pub(crate) deskew: Option<bool>,
+    pub(crate) auto_crop: Option<AutoCrop>,
+    pub(crate) skip_blank_pages: Option<bool>,
+    pub(crate) blank_page_sensitivity: Option<u8>,
+    pub(crate) multi_feed_detection: Option<bool>,
+    pub(crate) multi_feed_sensitivity: Option<u8>,
+    pub(crate) preview: Option<bool>,
+    pub(crate) format: Option<ScanFormat>,
+    pub(crate) compression: Option<Compression>,
+    pub(crate) jpeg_quality: Option<u8>,
+    pub(crate) pages: Option<u32>,
+    pub(crate) force: bool,
+    pub(crate) strict: bool,
+    pub(crate) output: Option<PathBuf>,
+}
+
+/// Alternative name for [`ScanOptions`].
+pub type ScanSettings = ScanOptions;
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn source(mut self, source: ScanSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Scans both sides of each sheet from the feeder. Turning it off again
+    /// goes back to single-sided feeder scanning.
+    pub fn duplex(mut self, duplex: bool) -> Self {
+        if duplex {
+            self.source = Some(ScanSource::FeederDuplex);
+        } else if self.source == Some(ScanSource::FeederDuplex) {
+            self.source = Some(ScanSource::Feeder);
+        }
+        self
+    }
+
+    /// Scans from the child item with this name rather than the first one,
+    /// for multi-function devices that expose each source as its own item.
+    /// The names are listed by [`WiaDevice::items`](crate::WiaDevice::items).
+    pub fn item(mut self, name: impl Into<String>) -> Self {
+        self.item = Some(name.into());
+        self
+    }
+
+    /// Sets the same resolution horizontally and vertically.
+    pub fn dpi(self, dpi: u32) -> Self {
+        self.x_dpi(dpi).y_dpi(dpi)
+    }
+
+    /// Sets the horizontal resolution alone, for the few devices that
+    /// support different resolutions on each axis.
+    pub fn x_dpi(mut self, dpi: u32) -> Self {
+        self.x_dpi = Some(dpi);
+        self
+    }
+
+    /// Sets the vertical resolution alone. See [`x_dpi`](Self::x_dpi).
+    pub fn y_dpi(mut self, dpi: u32) -> Self {
+        self.y_dpi = Some(dpi);
+        self
+    }
+
+    /// Same as [`dpi`](Self::dpi).
+    pub fn resolution(self, dpi: u32) -> Self {
+        self.dpi(dpi)
+    }
+
+    pub fn color_mode(mut self, color_mode: ColorMode) -> Self {
+        self.color_mode = Some(color_mode);
+        self
+    }
+
+    /// Same as [`color_mode`](Self::color_mode).
+    pub fn color(self, color_mode: ColorMode) -> Self {
+        self.color_mode(color_mode)
+    }
+
+    /// Adds size or quality hints to the intent written with the color
+    /// mode, see [`IntentFlags`]. Without a color mode the flags are
+    /// written alone, leaving the image type to the driver.
+    pub fn intent(mut self, flags: IntentFlags) -> Self {
+        self.intent = flags;
+        self
+    }
+
+    /// The intent flags to write: those set with [`intent`](Self::intent),
+    /// or else the hint [`jpeg_quality`](Self::jpeg_quality) maps to.
+    pub(crate) fn intent_flags(&self) -> IntentFlags {
+        match self.jpeg_quality {
+            _ if !self.intent.is_empty() => self.intent,
+            Some(quality) if quality <= 33 => IntentFlags::MINIMIZE_SIZE,
+            Some(quality) if quality >= 67 => IntentFlags::MAXIMIZE_QUALITY,
+            _ => IntentFlags::NONE,
+        }
+    }
+
+    /// Bits per pixel, such as 1 for OCR or 24 for photos. Set it after
+    /// choosing a color mode the depth makes sense for.
+    pub fn bit_depth(mut self, depth: u8) -> Self {
+        self.bit_depth = Some(depth);
+        self
+    }
+
+    /// The brightness above which pixels come out white in a
+    /// black-and-white scan. Lower it to keep light pencil from
+    /// disappearing. Other color modes ignore it, which the result reports
+    /// as a warning.
+    pub fn threshold(mut self, threshold: u8) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Has the driver pick the exposure itself, for photos. The scan fails
+    /// with [`WiaError::FeatureUnsupported`] if the device can't, and
+    /// [`validate`](Self::validate) rejects it alongside an explicit
+    /// brightness or contrast.
+    pub fn auto_exposure(mut self, auto_exposure: bool) -> Self {
+        self.auto_exposure = Some(auto_exposure);
+        self
+    }
+
+    /// Brightness in the driver's units, typically -1000..=1000 with 0 as
+    /// neutral. Values outside the device's range are clamped and reported
+    /// in [`ScanResult::warnings`].
+    pub fn brightness(mut self, brightness: i32) -> Self {
+        self.brightness = Some(Level::Absolute(brightness));
+        self
+    }
+
+    /// Brightness from -1.0 to 1.0, scaled to the device's range.
+    pub fn brightness_normalized(mut self, brightness: f32) -> Self {
+        self.brightness = Some(Level::Normalized(brightness));
+        self
+    }
+
+    /// Contrast in the driver's units. See [`brightness`](Self::brightness).
+    pub fn contrast(mut self, contrast: i32) -> Self {
+        self.contrast = Some(Level::Absolute(contrast));
+        self
+    }
+
+    /// Contrast from -1.0 to 1.0, scaled to the device's range.
+    pub fn contrast_normalized(mut self, contrast: f32) -> Self {
+        self.contrast = Some(Level::Normalized(contrast));
+        self
+    }
+
+    /// The paper size to scan. [`PageSize::Auto`] falls back to the
+    /// driver's current size, with a warning in [`ScanResult::warnings`],
+    /// when the device can't detect sizes.
+    pub fn page_size(mut self, page_size: PageSize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Scans only part of the bed. The region is in pixels at the
+    /// resolution set with [`dpi`](Self::dpi), or the driver's current one,
+    /// and is checked against the bed size before scanning.
+    pub fn region(mut self, region: ScanRegion) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Rotates the image, through `WIA_IPS_ROTATION` or, if the driver
+    /// doesn't support it, `WIA_IPS_ORIENTATION`. The property used is
+    /// reported in [`ScanResult::rotated_by`].
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = Some(rotation);
+        self
+    }
+
+    /// Tells the driver how pages are loaded, through
+    /// `WIA_IPS_ORIENTATION` or, failing that, `WIA_IPS_ROTATION`.
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = Some(orientation);
+        self
+    }
+
+    /// Has the driver straighten pages that went in at a slight angle. The
+    /// scan fails with [`WiaError::FeatureUnsupported`]
+    /// if the device can't.
+    pub fn deskew(mut self, deskew: bool) -> Self {
+        self.deskew = Some(deskew);
+        self
+    }
+
+    /// Has the driver crop the scan to the detected documents or photos.
+    /// With [`AutoCrop::MultiRegion`] each region is written to its own
+    /// file next to the output, numbered from `_002` after the first. The
+    /// scan fails with [`WiaError::FeatureUnsupported`] if the device can't
+    /// crop.
+    pub fn auto_crop(mut self, auto_crop: AutoCrop) -> Self {
+        self.auto_crop = Some(auto_crop);
+        self
+    }
+
+    /// Drops blank pages, such as the empty backs of a duplex scan. Drivers
+    /// that can't do it themselves get the pages checked after the transfer
+    /// instead, which works for BMP scans through
+    /// [`WiaDevice::scan_pages`](crate::WiaDevice::scan_pages) and
+    /// [`WiaDevice::scan_to_document`](crate::WiaDevice::scan_to_document).
+    /// Either way, [`ScanResult::blank_pages_skipped`] counts the pages the
+    /// crate dropped.
+    ///
+    /// The crate only checks pages of those multi-page scans; a single-page
+    /// scan is always kept, however blank, unless the driver drops it.
+    pub fn skip_blank_pages(mut self, skip: bool) -> Self {
+        self.skip_blank_pages = Some(skip);
+        self
+    }
+
+    /// How readily the driver treats a page as blank, in its own units,
+    /// when skipping blank pages. Ignored by drivers without the setting.
+    pub fn blank_page_sensitivity(mut self, sensitivity: u8) -> Self {
+        self.blank_page_sensitivity = Some(sensitivity);
+        self
+    }
+
+    /// Has the feeder watch for sheets pulled in together. A double feed
+    /// during a multi-page scan fails with [`WiaError::MultiFeedAfter`],
+    /// keeping the pages scanned before it. The scan fails with
+    /// [`WiaError::FeatureUnsupported`] if the device can't detect them.
+    pub fn multi_feed_detection(mut self, detect: bool) -> Self {
+        self.multi_feed_detection = Some(detect);
+        self
+    }
+
+    /// How readily the feeder reports a double feed, in the driver's own
+    /// units, when multi-feed detection is on.
+    pub fn multi_feed_sensitivity(mut self, sensitivity: u8) -> Self {
+        self.multi_feed_sensitivity = Some(sensitivity);
+        self
+    }
+
+    /// Asks for a quick preview pass rather than a final scan. Devices
+    /// without a preview mode scan at 75 DPI instead, unless a resolution
+    /// is set, with a warning in [`ScanResult::warnings`].
+    pub fn preview(mut self, preview: bool) -> Self {
+        self.preview = Some(preview);
+        self
+    }
+
+    pub fn format(mut self, format: ScanFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Compresses the image data, for smaller archives. The compression
+    /// has to fit the [`format`](Self::format), which
+    /// [`validate`](Self::validate) checks, and the driver has to list it
+    /// as supported.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Asks for JPEG compression at the given quality, from 1 to 100.
+    ///
+    /// WIA has no standard quality property, so the number reaches the
+    /// driver as an intent hint instead: 33 and below asks it to minimize
+    /// size, 67 and above to maximize quality, and anything in between
+    /// leaves its default. Flags set with [`intent`](Self::intent) take
+    /// precedence. Only JPEG, TIFF and PDF output can hold JPEG data, so
+    /// other formats fail [`validate`](Self::validate).
+    pub fn jpeg_quality(mut self, quality: u8) -> Self {
+        self.jpeg_quality = Some(quality);
+        self
+    }
+
+    /// How many pages to take from the feeder, with `0` meaning all of
+    /// them. Asking for more pages than are loaded just ends the scan when
+    /// the feeder runs out.
+    pub fn pages(mut self, pages: u32) -> Self {
+        self.pages = Some(pages);
+        self
+    }
+
+    /// Skips checking the settings against the values the driver reports
+    /// before scanning, for drivers that report them wrong. A value the
+    /// driver really won't take still fails when it's written.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Fails the scan with [`WiaError::InvalidSettings`] when the driver
+    /// reports using a different value than was asked for, such as a
+    /// resolution it quietly rounded down, instead of scanning anyway. The
+    /// values used are in [`ScanResult::effective`] either way.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// The file the scan is written to. Defaults to `scanned_document` in
+    /// the current directory, with an extension matching the format.
+    pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    pub(crate) fn output_path(&self) -> PathBuf {
+        match (&self.output, self.format) {
+            (Some(output), _) => output.clone(),
+            (None, Some(format)) => {
+                PathBuf::from(DEFAULT_OUTPUT_STEM).with_extension(format.extension())
+            }
+            (None, None) => PathBuf::from(DEFAULT_OUTPUT_STEM).with_extension("pdf"),
+        }
+    }
+
+    /// Finishes a chain of setters, checking the result with
+    /// [`validate`](Self::validate) so a bad preset is caught where it's
+    /// built rather than when it's used.
+    pub fn build(self) -> Result<Self> {
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Rejects combinations that can never work, before any COM call is made.
+    pub fn validate(&self) -> Result<()> {
+        if self.x_dpi == Some(0) || self.y_dpi == Some(0) {
+            return Err(WiaError::InvalidOption(
+                "Resolution must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.bit_depth == Some(0) {
+            return Err(WiaError::InvalidOption(
+                "Bit depth must be greater than zero".to_string(),
+            ));
+        }
+
+        if let Some(PageSize::Custom { width, height }) = self.page_size
+            && (width == 0 || height == 0)
+        {
+            return Err(WiaError::InvalidOption(
+                "Custom page size must not be empty".to_string(),
+            ));
+        }
+
+        if let Some(region) = self.region
+            && (region.width == 0 || region.height == 0)
+        {
+            return Err(WiaError::InvalidOption(
+                "Scan region must not be empty".to_string(),
+            ));
+        }
+
+        if self.auto_exposure == Some(true)
+            && (self.brightness.is_some() || self.contrast.is_some())
+        {
+            return Err(WiaError::InvalidOption(
+                "Brightness and contrast can't be set with automatic exposure".to_string(),
+            ));
+        }
+
+        for (name, level) in [("Brightness", self.brightness), ("Contrast", self.contrast)] {
+            if let Some(Level::Normalized(value)) = level
+                && value.is_nan()
+            {
+                return Err(WiaError::InvalidOption(format!("{} must be a number", name)));
+            }
+        }
+
+        if let Some(quality) = self.jpeg_quality
+            && !(1..=100).contains(&quality)
+        {
+            return Err(WiaError::InvalidOption(format!(
+                "JPEG quality {} is outside 1..=100",
+                quality
+            )));
+        }
+
+        if let (Some(compression), Some(format)) = (self.compression, self.format)
+            && !compression.fits(format)
+        {
+            return Err(WiaError::InvalidOption(format!(
+                "{:?} compression can't be used with {:?} output",
+                compression, format
+            )));
+        }
+
+        // A quality only means something to JPEG data, which TIFF and PDF
+        // can hold too
+        if self.jpeg_quality.is_some()
+            && (self.compression.is_some_and(|compression| compression != Compression::Jpeg)
+                || self.format.is_some_and(|format| !Compression::Jpeg.fits(format)))
+        {
+            return Err(WiaError::InvalidOption(
+                "JPEG quality needs JPEG compression and JPEG, TIFF or PDF output".to_string(),
+            ));
+        }
+
+        let output_path = self.output_path();
+        if output_path.as_os_str().is_empty() {
+            return Err(WiaError::InvalidOption(
+                "Output path must not be empty".to_string(),
+            ));
+        }
+
+        // Fail early rather than letting idtGetData return an opaque HRESULT
+        if let Some(parent) = output_path.parent()
+            && !parent.as_os_str().is_empty()
+            && !parent.is_dir()
+        {
+            return Err(WiaError::InvalidOption(format!(
+                "Output directory does not exist: {}",
+                parent.display()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// What a finished scan produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanResult {
+    /// Absolute path of the written file.
+    pub path: PathBuf,
+    /// Size of the written file.
+    pub bytes: u64,
+    /// Number of pages transferred.
+    pub pages: u32,
+    /// Blank pages the crate detected and left out. Pages the driver
+    /// dropped itself never reach the crate and aren't counted.
+    pub blank_pages_skipped: u32,
+    /// Number of regions [`AutoCrop::MultiRegion`] split the scan into, 1
+    /// otherwise.
+    pub regions: u32,
+    /// Every file the scan wrote, starting with `path`. Only holds more
+    /// than `path` when the scan was split into regions, or for
+    /// [`WiaDevice::scan_pages`](crate::WiaDevice::scan_pages), which
+    /// writes a file per page.
+    pub paths: Vec<PathBuf>,
+    /// The `WIA_IPA_FORMAT` the driver reported after the transfer, or the
+    /// zero GUID when WIA has no format for the written file.
+    pub format: GUID,
+    /// The color mode the driver reported after the transfer, `None` if it
+    /// didn't report one this crate knows.
+    pub color_mode: Option<ColorMode>,
+    /// The bits per pixel the driver reported after the transfer.
+    pub bit_depth: Option<u8>,
+    /// Settings that couldn't be applied exactly as asked, such as a
+    /// brightness clamped to the device's range.
+    pub warnings: Vec<String>,
+    /// The property a requested rotation or orientation was applied
+    /// through, `None` if neither was asked for.
+    pub rotated_by: Option<RotationProperty>,
+    /// Whether both sides were scanned, in which case pages alternate
+    /// front and back.
+    pub duplex: bool,
+    /// Which try succeeded when scanning under a
+    /// [`RetryPolicy`](crate::RetryPolicy), 1 otherwise.
+    pub attempt: u32,
+    /// The settings the driver reported once the options were applied.
+    pub effective: EffectiveSettings,
+}
+
+/// Settings as the driver reported them after a scan was configured, which
+/// can differ from what was asked for when a driver clamps or ignores a
+/// write. Each is `None` if the driver didn't report it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EffectiveSettings {
+    pub x_dpi: Option<u32>,
+    pub y_dpi: Option<u32>,
+    pub color_mode: Option<ColorMode>,
+    pub bit_depth: Option<u8>,
+    pub threshold: Option<u8>,
+    pub brightness: Option<i32>,
+    pub contrast: Option<i32>,
+}
@@ -0,0 +1,56 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::options::ScanOptions;
+use crate::{Result, WiaError};
+
+/// A named set of [`ScanOptions`], for kinds of documents that are scanned
+/// the same way every time. Requires the `serde` feature.
+///
+/// A profile saved on one machine may ask for settings another scanner
+/// can't do; [`WiaDevice::check_options`](crate::WiaDevice::check_options)
+/// lists which ones before scanning.
+///
+/// ```no_run
+/// use wia_rs::{ColorMode, ScanOptions, ScanProfile, load_profiles, save_profiles};
+///
+/// let profiles = vec![
+///     ScanProfile::new("Receipts", ScanOptions::new().dpi(200).color_mode(ColorMode::Grayscale)),
+///     ScanProfile::new("Photos", ScanOptions::new().dpi(600).color_mode(ColorMode::Color)),
+/// ];
+/// save_profiles("profiles.json", &profiles)?;
+///
+/// for profile in load_profiles("profiles.json")? {
+///     println!("{}", profile.name);
+/// }
+/// # Ok::<(), wia_rs::WiaError>(())
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanProfile {
+    pub name: String,
+    pub options: ScanOptions,
+}
+
+impl ScanProfile {
+    pub fn new(name: impl Into<String>, options: ScanOptions) -> Self {
+        ScanProfile {
+            name: name.into(),
+            options,
+        }
+    }
+}
+
+/// Writes the profiles to `path` as JSON, replacing the file.
+pub fn save_profiles(path: impl AsRef<Path>, profiles: &[ScanProfile]) -> Result<()> {
+    let json = serde_json::to_string_pretty(profiles).map_err(|e| WiaError::Io(e.to_string()))?;
+    std::fs::write(path, json).map_err(|e| WiaError::Io(e.to_string()))
+}
+
+/// Reads profiles written by [`save_profiles`]. Options missing from the
+/// file are left unset, so files from older versions still load.
+pub fn load_profiles(path: impl AsRef<Path>) -> Result<Vec<ScanProfile>> {
+    let json = std::fs::read_to_string(path).map_err(|e| WiaError::Io(e.to_string()))?;
+    serde_json::from_str(&json)
+        .map_err(|e| WiaError::InvalidOption(format!("Invalid scan profiles: {}", e)))
+}
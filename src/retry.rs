@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use windows::{Win32::Devices::ImageAcquisition::*, core::HRESULT};
+
+use crate::{Result, WiaError};
+
+/// How often to retry an operation that failed with a transient error.
+///
+/// Drivers commonly report busy, warming up or communication errors for a
+/// second or two after a device wakes from sleep. Only those are retried by
+/// default; errors such as a paper jam are returned straight away.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use wia_rs::{RetryPolicy, ScanSource, WIAScanManager};
+/// use windows::Win32::Devices::ImageAcquisition::{WIA_ERROR_BUSY, WIA_ERROR_WARMING_UP};
+///
+/// let policy = RetryPolicy {
+///     attempts: 5,
+///     delay: Duration::from_secs(1),
+///     ..RetryPolicy::default()
+/// }
+/// .retry_on([WIA_ERROR_BUSY, WIA_ERROR_WARMING_UP]);
+///
+/// let manager = WIAScanManager::init()?;
+/// let device = manager.default_device()?;
+/// manager.scan_document_with_retry(&device.id, ScanSource::Flatbed, "scan.bmp", &policy)?;
+/// # Ok::<(), wia_rs::WiaError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Total number of tries, including the first one.
+    pub attempts: u32,
+    /// Wait before the first retry.
+    pub delay: Duration,
+    /// Factor the wait is multiplied by after every retry. A product that
+    /// isn't a valid wait, from a negative or NaN factor or an overflow,
+    /// leaves the wait as it was.
+    pub backoff: f32,
+    /// The error codes worth retrying, as returned by
+    /// [`WiaError::code`].
+    pub retry_on: Vec<HRESULT>,
+}
+
+impl RetryPolicy {
+    /// A policy that tries once and never retries.
+    pub fn none() -> Self {
+        RetryPolicy {
+            attempts: 1,
+            delay: Duration::ZERO,
+            backoff: 1.0,
+            retry_on: Vec::new(),
+        }
+    }
+
+    /// Replaces the error codes that are retried.
+    pub fn retry_on(mut self, codes: impl IntoIterator<Item = HRESULT>) -> Self {
+        self.retry_on = codes.into_iter().collect();
+        self
+    }
+
+    fn retries(&self, error: &WiaError) -> bool {
+        error.code().is_some_and(|code| self.retry_on.contains(&code))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            delay: Duration::from_millis(500),
+            backoff: 2.0,
+            // The errors WiaError::is_transient covers
+            retry_on: vec![WIA_ERROR_BUSY, WIA_ERROR_WARMING_UP, WIA_ERROR_DEVICE_COMMUNICATION],
+        }
+    }
+}
+
+/// Runs `operation` under `policy`, returning its result along with the
+/// attempt (starting at 1) that succeeded.
+pub(crate) fn with_retry<T>(
+    policy: &RetryPolicy,
+    mut operation: impl FnMut() -> Result<T>,
+) -> Result<(T, u32)> {
+    let mut delay = policy.delay;
+    let mut attempt = 1;
+
+    loop {
+        match operation() {
+            Ok(value) => return Ok((value, attempt)),
+            Err(e) if policy.retries(&e) && attempt < policy.attempts => {
+                log::debug!("Attempt {} failed with {:?}, retrying in {:?}", attempt, e, delay);
+                std::thread::sleep(delay);
+                delay = next_delay(delay, policy.backoff);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// `delay` scaled by `backoff`, or `delay` itself if that isn't a valid
+/// `Duration`. `Duration::mul_f32` would panic instead.
+fn next_delay(delay: Duration, backoff: f32) -> Duration {
+    Duration::try_from_secs_f32(delay.as_secs_f32() * backoff).unwrap_or(delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_scales_the_delay() {
+        let delay = Duration::from_millis(500);
+        assert_eq!(next_delay(delay, 2.0), Duration::from_secs(1));
+        assert_eq!(next_delay(delay, 1.0), delay);
+    }
+
+    #[test]
+    fn invalid_backoff_keeps_the_delay() {
+        let delay = Duration::from_millis(500);
+        assert_eq!(next_delay(delay, -1.0), delay);
+        assert_eq!(next_delay(delay, f32::NAN), delay);
+        assert_eq!(next_delay(delay, f32::INFINITY), delay);
+        assert_eq!(next_delay(Duration::MAX, 2.0), Duration::MAX);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::options::{ScanOptions, ScanResult};
+use crate::{DeviceId, Result, WIAScanManager, WiaDevice, WiaError};
+
+/// A device kept open for a run of scans.
+///
+/// Connecting and finding the scan item only happens once, so scanning a
+/// stack of separate documents doesn't pay for it on every page. If the
+/// driver drops the item in between (`WIA_ERROR_ITEM_DELETED`, e.g. after
+/// the scanner went to sleep) the device is reopened once and the scan
+/// retried.
+///
+/// ```no_run
+/// use wia_rs::{ScanOptions, WIAScanManager};
+///
+/// let manager = WIAScanManager::init()?;
+/// let mut session = manager.session("{6BDD1FC6-810F-11D0-BEC7-08002BE2092F}\\0000")?;
+/// for i in 1..=3 {
+///     session.scan_next(&ScanOptions::new().output(format!("doc_{}.bmp", i)))?;
+/// }
+/// # Ok::<(), wia_rs::WiaError>(())
+/// ```
+pub struct ScanSession<'m> {
+    manager: &'m WIAScanManager,
+    device: WiaDevice<'m>,
+}
+
+impl<'m> ScanSession<'m> {
+    pub(crate) fn open(manager: &'m WIAScanManager, device_id: impl Into<DeviceId>) -> Result<Self> {
+        let device = manager.open_device(device_id)?;
+        // Found now so the first scan doesn't pay for it; the device keeps it
+        device.scan_item()?;
+
+        Ok(ScanSession { manager, device })
+    }
+
+    /// The device this session scans from.
+    pub fn device(&self) -> &WiaDevice<'m> {
+        &self.device
+    }
+
+    /// Scans the next document with the given options, the same way
+    /// [`WiaDevice::scan_with_options`] does.
+    pub fn scan_next(&mut self, options: &ScanOptions) -> Result<ScanResult> {
+        match self.device.scan_with_options(options) {
+            Err(WiaError::ItemDeleted) => {
+                log::info!("Scan item was deleted, reopening {}", self.device.id());
+                *self = Self::open(self.manager, self.device.id())?;
+                self.device.scan_with_options(options)
+            }
+            result => result,
+        }
+    }
+}
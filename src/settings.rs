@@ -0,0 +1,589 @@
+use windows::Win32::Devices::ImageAcquisition::*;
+
+use crate::backend::ItemProperties;
+use crate::util::WiaValue;
+use crate::{
+    AutoCrop, ColorMode, Compression, IntentFlags, Orientation, PageSize, Resolutions, Result,
+    Rotation, RotationProperty, WiaError,
+};
+
+/// Checks `value` against the valid values the driver reports for a
+/// property. Drivers that don't report a range or list are trusted as is.
+pub(crate) fn validate_i4(
+    props: &impl ItemProperties,
+    prop_id: u32,
+    name: &str,
+    value: i32,
+) -> Result<()> {
+    // Not every driver exposes attributes, in which case the write decides
+    let Ok((flags, values)) = props.property_attributes(prop_id) else {
+        return Ok(());
+    };
+
+    if flags & WIA_PROP_RANGE != 0 && values.len() >= WIA_RANGE_NUM_ELEMS as usize {
+        let min = values[WIA_RANGE_MIN as usize];
+        let max = values[WIA_RANGE_MAX as usize];
+        if value < min || value > max {
+            return Err(WiaError::InvalidOption(format!(
+                "{} {} is outside the supported range {}..={}",
+                name, value, min, max
+            )));
+        }
+    } else if flags & WIA_PROP_LIST != 0 && values.len() > WIA_LIST_VALUES as usize {
+        let supported = &values[WIA_LIST_VALUES as usize..];
+        if !supported.contains(&value) {
+            return Err(WiaError::UnsupportedValue {
+                property: name.to_string(),
+                value,
+                supported: supported.to_vec(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// The range the driver reports for a property, if it reports one.
+fn reported_range(props: &impl ItemProperties, prop_id: u32) -> Option<(i32, i32)> {
+    match props.property_attributes(prop_id) {
+        Ok((flags, values))
+            if flags & WIA_PROP_RANGE != 0 && values.len() >= WIA_RANGE_NUM_ELEMS as usize =>
+        {
+            Some((values[WIA_RANGE_MIN as usize], values[WIA_RANGE_MAX as usize]))
+        }
+        _ => None,
+    }
+}
+
+/// Clamps `value` into the range the driver reports for a property. Values
+/// are passed through unchanged when no range is reported.
+fn clamp_i4(props: &impl ItemProperties, prop_id: u32, value: i32) -> i32 {
+    reported_range(props, prop_id).map_or(value, |(min, max)| value.clamp(min, max))
+}
+
+/// Maps `value` from -1.0..=1.0 onto the range the driver reports for a
+/// property, clamping it first.
+fn scale_normalized(props: &impl ItemProperties, prop_id: u32, value: f32) -> i32 {
+    // Drivers that don't report a range nearly always use the WIA default
+    let (min, max) = reported_range(props, prop_id).unwrap_or((-1000, 1000));
+    let position = (value.clamp(-1.0, 1.0) + 1.0) / 2.0;
+    min + ((max - min) as f32 * position).round() as i32
+}
+
+fn write_clamped(item: &impl ItemProperties, prop_id: u32, value: i32) -> Result<i32> {
+    let value = clamp_i4(item, prop_id, value);
+    item.write_i4(prop_id, value)?;
+    Ok(value)
+}
+
+fn write_normalized(item: &impl ItemProperties, prop_id: u32, value: f32) -> Result<i32> {
+    let value = scale_normalized(item, prop_id, value);
+    item.write_i4(prop_id, value)?;
+    Ok(value)
+}
+
+/// The resolutions a scan item accepts, read from the attributes of
+/// `WIA_IPS_XRES`. Drivers that report neither a list nor a range get
+/// their current resolution back as the only choice.
+pub fn supported_resolutions(item: &impl ItemProperties) -> Result<Resolutions> {
+    let to_u32 = |value: i32| value.max(0) as u32;
+
+    if let Ok((flags, values)) = item.property_attributes(WIA_IPS_XRES) {
+        if flags & WIA_PROP_RANGE != 0 && values.len() >= WIA_RANGE_NUM_ELEMS as usize {
+            return Ok(Resolutions::Range {
+                min: to_u32(values[WIA_RANGE_MIN as usize]),
+                max: to_u32(values[WIA_RANGE_MAX as usize]),
+                step: to_u32(values[WIA_RANGE_STEP as usize]),
+            });
+        }
+        if flags & WIA_PROP_LIST != 0 && values.len() > WIA_LIST_VALUES as usize {
+            let list = values[WIA_LIST_VALUES as usize..].iter().copied().map(to_u32);
+            return Ok(Resolutions::List(list.collect()));
+        }
+    }
+
+    let current = item.read_i4(WIA_IPS_XRES)?;
+    Ok(Resolutions::List(vec![to_u32(current)]))
+}
+
+/// Sets the horizontal and vertical resolution of a scan item.
+///
+/// The value is checked against the resolutions the driver reports before
+/// anything is written.
+pub fn set_resolution(item: &impl ItemProperties, dpi: u32) -> Result<()> {
+    set_resolution_xy(item, dpi, dpi)
+}
+
+/// Sets different horizontal and vertical resolutions on a scan item, for
+/// devices that support asymmetric resolutions. Both values are checked
+/// before either is written.
+pub fn set_resolution_xy(item: &impl ItemProperties, x_dpi: u32, y_dpi: u32) -> Result<()> {
+    let to_i4 = |dpi: u32| {
+        i32::try_from(dpi)
+            .map_err(|_| WiaError::InvalidOption(format!("Resolution {} is out of range", dpi)))
+    };
+    let (x_dpi, y_dpi) = (to_i4(x_dpi)?, to_i4(y_dpi)?);
+
+    validate_i4(item, WIA_IPS_XRES, "Horizontal resolution", x_dpi)?;
+    validate_i4(item, WIA_IPS_YRES, "Vertical resolution", y_dpi)?;
+
+    item.write_i4(WIA_IPS_XRES, x_dpi)?;
+    item.write_i4(WIA_IPS_YRES, y_dpi)
+}
+
+/// Sets the color mode (color, grayscale or black-and-white) of a scan item
+/// through both `WIA_IPS_CUR_INTENT` and `WIA_IPA_DATATYPE`.
+///
+/// Some drivers only honor one of the two, so this only fails when neither
+/// is accepted. The intent is written first because drivers reset other
+/// properties, including the data type and resolution, to suit it.
+pub fn set_color_mode(item: &impl ItemProperties, color_mode: ColorMode) -> Result<()> {
+    set_color_mode_with_intent(item, color_mode, IntentFlags::NONE)
+}
+
+/// Like [`set_color_mode`], with size or quality hints ORed into the same
+/// `WIA_IPS_CUR_INTENT` write as the image type.
+pub fn set_color_mode_with_intent(
+    item: &impl ItemProperties,
+    color_mode: ColorMode,
+    flags: IntentFlags,
+) -> Result<()> {
+    let data_type = color_mode.data_type() as i32;
+
+    let intent = (color_mode.intent() | flags.bits()) as i32;
+    let intent = item.write_i4(WIA_IPS_CUR_INTENT, intent);
+    let data_type = validate_i4(item, WIA_IPA_DATATYPE, "Data type", data_type)
+        .and_then(|()| item.write_i4(WIA_IPA_DATATYPE, data_type));
+
+    match (intent, data_type) {
+        (Err(_), Err(e)) => Err(e),
+        (Err(e), Ok(())) => {
+            log::debug!("Driver didn't accept the intent, relying on the data type: {:?}", e);
+            Ok(())
+        }
+        (Ok(()), Err(e)) => {
+            log::warn!("Driver didn't accept the data type, relying on the intent: {:?}", e);
+            Ok(())
+        }
+        (Ok(()), Ok(())) => Ok(()),
+    }
+}
+
+/// Writes size or quality hints to `WIA_IPS_CUR_INTENT` without an image
+/// type, leaving the color mode to the driver.
+pub fn set_intent(item: &impl ItemProperties, flags: IntentFlags) -> Result<()> {
+    item.write_i4(WIA_IPS_CUR_INTENT, flags.bits() as i32)
+}
+
+/// Sets the number of bits per pixel of a scan item, such as 1 for
+/// black-and-white or 24 for color.
+///
+/// Depths the driver doesn't list are rejected with
+/// [`WiaError::UnsupportedValue`] before anything is written.
+pub fn set_bit_depth(item: &impl ItemProperties, depth: u8) -> Result<()> {
+    let depth = depth as i32;
+
+    validate_i4(item, WIA_IPA_DEPTH, "Bit depth", depth)?;
+
+    item.write_i4(WIA_IPA_DEPTH, depth)
+}
+
+/// Sets the black-and-white threshold of a scan item: pixels brighter than
+/// it come out white. Drivers only use it for threshold scans, see
+/// [`ColorMode::BlackAndWhite`].
+///
+/// The value is checked against the range the driver reports before
+/// anything is written.
+pub fn set_threshold(item: &impl ItemProperties, threshold: u8) -> Result<()> {
+    let threshold = threshold as i32;
+
+    validate_i4(item, WIA_IPS_THRESHOLD, "Threshold", threshold)?;
+
+    item.write_i4(WIA_IPS_THRESHOLD, threshold)
+}
+
+/// Turns the driver's automatic deskew on or off, which straightens pages
+/// that went through the feeder at a slight angle.
+///
+/// Fails with [`WiaError::FeatureUnsupported`] if the scan item doesn't
+/// expose `WIA_IPS_AUTO_DESKEW`.
+pub fn set_deskew(item: &impl ItemProperties, enabled: bool) -> Result<()> {
+    // Reading first tells a missing property apart from a rejected value
+    match item.read_property(WIA_IPS_AUTO_DESKEW) {
+        Ok(WiaValue::I4(_)) => {}
+        _ => return Err(WiaError::FeatureUnsupported("Automatic deskew".to_string())),
+    }
+
+    let value = if enabled { WIA_AUTO_DESKEW_ON } else { WIA_AUTO_DESKEW_OFF };
+    item.write_i4(WIA_IPS_AUTO_DESKEW, value as i32)
+}
+
+/// Sets whether the driver crops scans of an item to the documents or
+/// photos it detects, turning segmentation on for
+/// [`AutoCrop::MultiRegion`].
+///
+/// Fails with [`WiaError::FeatureUnsupported`] if the scan item doesn't
+/// expose `WIA_IPS_AUTO_CROP`.
+pub fn set_auto_crop(item: &impl ItemProperties, auto_crop: AutoCrop) -> Result<()> {
+    match item.read_property(WIA_IPS_AUTO_CROP) {
+        Ok(WiaValue::I4(_)) => {}
+        _ => return Err(WiaError::FeatureUnsupported("Automatic cropping".to_string())),
+    }
+    item.write_i4(WIA_IPS_AUTO_CROP, auto_crop.wia_value() as i32)?;
+
+    // Drivers that split regions in hardware don't expose segmentation
+    let segmentation = if auto_crop == AutoCrop::MultiRegion {
+        WIA_USE_SEGMENTATION_FILTER
+    } else {
+        WIA_DONT_USE_SEGMENTATION_FILTER
+    };
+    if let Err(e) = item.write_i4(WIA_IPS_SEGMENTATION, segmentation as i32) {
+        log::debug!("Failed to set segmentation: {:?}", e);
+    }
+
+    Ok(())
+}
+
+/// Switches the driver's automatic exposure on or off.
+///
+/// WIA has no scanner property for it, so this uses the exposure mode
+/// cameras have (`WIA_DPC_EXPOSURE_MODE`), which several scanner drivers
+/// expose on their scan item as well. Fails with
+/// [`WiaError::FeatureUnsupported`] if the item doesn't.
+pub fn set_auto_exposure(item: &impl ItemProperties, enabled: bool) -> Result<()> {
+    match item.read_property(WIA_DPC_EXPOSURE_MODE) {
+        Ok(WiaValue::I4(_)) => {}
+        _ => return Err(WiaError::FeatureUnsupported("Automatic exposure".to_string())),
+    }
+
+    let value = if enabled { EXPOSUREMODE_AUTO } else { EXPOSUREMODE_MANUAL };
+    validate_i4(item, WIA_DPC_EXPOSURE_MODE, "Exposure mode", value as i32)?;
+    item.write_i4(WIA_DPC_EXPOSURE_MODE, value as i32)
+}
+
+/// Sets how the driver compresses an item's image data.
+///
+/// Fails with [`WiaError::FeatureUnsupported`] if the scan item doesn't
+/// expose `WIA_IPA_COMPRESSION`, and with [`WiaError::UnsupportedValue`] if
+/// the driver doesn't list `compression`.
+pub fn set_compression(item: &impl ItemProperties, compression: Compression) -> Result<()> {
+    match item.read_property(WIA_IPA_COMPRESSION) {
+        Ok(WiaValue::I4(_)) => {}
+        _ => return Err(WiaError::FeatureUnsupported("Compression".to_string())),
+    }
+
+    let value = compression.wia_value() as i32;
+    validate_i4(item, WIA_IPA_COMPRESSION, "Compression", value)?;
+    item.write_i4(WIA_IPA_COMPRESSION, value)
+}
+
+/// Has the driver drop blank pages from feeder scans, or keep them again.
+/// `sensitivity` is written to `WIA_IPS_BLANK_PAGES_SENSITIVITY` when given
+/// and the driver has it, checked against the range it reports.
+///
+/// Fails with [`WiaError::FeatureUnsupported`] if the scan item doesn't
+/// expose `WIA_IPS_BLANK_PAGES`.
+pub fn set_blank_page_skipping(
+    item: &impl ItemProperties,
+    enabled: bool,
+    sensitivity: Option<u8>,
+) -> Result<()> {
+    match item.read_property(WIA_IPS_BLANK_PAGES) {
+        Ok(WiaValue::I4(_)) => {}
+        _ => return Err(WiaError::FeatureUnsupported("Blank page detection".to_string())),
+    }
+
+    let value = if enabled {
+        WIA_BLANK_PAGE_DISCARD
+    } else {
+        WIA_BLANK_PAGE_DETECTION_DISABLED
+    };
+    item.write_i4(WIA_IPS_BLANK_PAGES, value as i32)?;
+
+    if let Some(sensitivity) = sensitivity.filter(|_| enabled) {
+        if item.read_property(WIA_IPS_BLANK_PAGES_SENSITIVITY).is_err() {
+            log::debug!("Driver has no blank page sensitivity, keeping its default");
+            return Ok(());
+        }
+        let sensitivity = sensitivity as i32;
+        validate_i4(
+            item,
+            WIA_IPS_BLANK_PAGES_SENSITIVITY,
+            "Blank page sensitivity",
+            sensitivity,
+        )?;
+        item.write_i4(WIA_IPS_BLANK_PAGES_SENSITIVITY, sensitivity)?;
+    }
+
+    Ok(())
+}
+
+/// Turns the feeder's multi-feed detection on or off. With it on, a double
+/// feed stops the scan with an error. `sensitivity` is written to
+/// `WIA_IPS_MULTI_FEED_SENSITIVITY` when given, checked against the range
+/// the driver reports.
+///
+/// Fails with [`WiaError::FeatureUnsupported`] if the scan item doesn't
+/// expose `WIA_IPS_MULTI_FEED`.
+pub fn set_multi_feed_detection(
+    item: &impl ItemProperties,
+    enabled: bool,
+    sensitivity: Option<u8>,
+) -> Result<()> {
+    match item.read_property(WIA_IPS_MULTI_FEED) {
+        Ok(WiaValue::I4(_)) => {}
+        _ => return Err(WiaError::FeatureUnsupported("Multi-feed detection".to_string())),
+    }
+
+    let value = if enabled {
+        WIA_MULTI_FEED_DETECT_STOP_ERROR
+    } else {
+        WIA_MULTI_FEED_DETECT_DISABLED
+    };
+    item.write_i4(WIA_IPS_MULTI_FEED, value as i32)?;
+
+    if let Some(sensitivity) = sensitivity.filter(|_| enabled) {
+        let sensitivity = sensitivity as i32;
+        validate_i4(
+            item,
+            WIA_IPS_MULTI_FEED_SENSITIVITY,
+            "Multi-feed sensitivity",
+            sensitivity,
+        )?;
+        item.write_i4(WIA_IPS_MULTI_FEED_SENSITIVITY, sensitivity)?;
+    }
+
+    Ok(())
+}
+
+/// Switches a scan item between quick preview scans and final ones.
+/// Drivers may lower the quality of a preview and skip calibrating for it.
+///
+/// Fails with [`WiaError::FeatureUnsupported`] if the scan item doesn't
+/// expose `WIA_IPS_PREVIEW`.
+pub fn set_preview(item: &impl ItemProperties, preview: bool) -> Result<()> {
+    match item.read_property(WIA_IPS_PREVIEW) {
+        Ok(WiaValue::I4(_)) => {}
+        _ => return Err(WiaError::FeatureUnsupported("Preview scanning".to_string())),
+    }
+
+    let value = if preview { WIA_PREVIEW_SCAN } else { WIA_FINAL_SCAN };
+    item.write_i4(WIA_IPS_PREVIEW, value as i32)
+}
+
+/// Sets the brightness of a scan item, clamped to the range the driver
+/// reports. Drivers typically use -1000..=1000 with 0 as neutral.
+///
+/// Returns the value written, which differs from `value` if it was clamped.
+pub fn set_brightness(item: &impl ItemProperties, value: i32) -> Result<i32> {
+    write_clamped(item, WIA_IPS_BRIGHTNESS, value)
+}
+
+/// Sets the brightness of a scan item from -1.0 (darkest) to 1.0
+/// (brightest), scaled to the range the driver reports. Returns the value
+/// written.
+pub fn set_brightness_normalized(item: &impl ItemProperties, value: f32) -> Result<i32> {
+    write_normalized(item, WIA_IPS_BRIGHTNESS, value)
+}
+
+/// Sets the contrast of a scan item, clamped to the range the driver
+/// reports. Drivers typically use -1000..=1000 with 0 as neutral.
+///
+/// Returns the value written, which differs from `value` if it was clamped.
+pub fn set_contrast(item: &impl ItemProperties, value: i32) -> Result<i32> {
+    write_clamped(item, WIA_IPS_CONTRAST, value)
+}
+
+/// Sets the contrast of a scan item from -1.0 to 1.0, scaled to the range
+/// the driver reports. Returns the value written.
+pub fn set_contrast_normalized(item: &impl ItemProperties, value: f32) -> Result<i32> {
+    write_normalized(item, WIA_IPS_CONTRAST, value)
+}
+
+/// Sets the paper size of a scan item through `WIA_IPS_PAGE_SIZE`, and
+/// the scan extents to match at the item's current resolution. Custom sizes
+/// also set `WIA_IPS_PAGE_WIDTH` and `WIA_IPS_PAGE_HEIGHT`.
+///
+/// Set the resolution first, since the extents are measured in its pixels.
+/// Drivers without the standard page sizes get the extents alone. Sizes
+/// neither fits, including [`PageSize::Auto`] on devices that can't detect
+/// sizes, are rejected before anything is written.
+pub fn set_page_size(item: &impl ItemProperties, page_size: PageSize) -> Result<()> {
+    let named = write_named_page_size(item, page_size);
+    // Only the driver knows how big an auto-detected page is
+    let Some((width, height)) = page_size.dimensions() else {
+        return named;
+    };
+
+    match (named, write_page_extents(item, width, height)) {
+        (Ok(()), Ok(())) => Ok(()),
+        (Ok(()), Err(e)) => {
+            log::debug!("Driver didn't accept the extents for {:?}: {:?}", page_size, e);
+            Ok(())
+        }
+        (Err(e), Ok(())) => {
+            log::debug!("Driver didn't accept page size {:?}, set extents only: {:?}", page_size, e);
+            Ok(())
+        }
+        (Err(e), Err(_)) => Err(e),
+    }
+}
+
+fn write_named_page_size(props: &impl ItemProperties, page_size: PageSize) -> Result<()> {
+    let page = page_size.wia_page() as i32;
+
+    validate_i4(props, WIA_IPS_PAGE_SIZE, "Page size", page)?;
+
+    if let PageSize::Custom { width, height } = page_size {
+        let to_i4 = |value: u32| {
+            i32::try_from(value)
+                .map_err(|_| WiaError::InvalidOption(format!("Page size {} is out of range", value)))
+        };
+        let (width, height) = (to_i4(width)?, to_i4(height)?);
+
+        validate_i4(props, WIA_IPS_PAGE_WIDTH, "Page width", width)?;
+        validate_i4(props, WIA_IPS_PAGE_HEIGHT, "Page height", height)?;
+
+        props.write_i4(WIA_IPS_PAGE_SIZE, page)?;
+        props.write_i4(WIA_IPS_PAGE_WIDTH, width)?;
+        return props.write_i4(WIA_IPS_PAGE_HEIGHT, height);
+    }
+
+    props.write_i4(WIA_IPS_PAGE_SIZE, page)
+}
+
+/// Writes the extents for a page of `width` by `height` thousandths of an
+/// inch at the item's current resolution.
+fn write_page_extents(props: &impl ItemProperties, width: u32, height: u32) -> Result<()> {
+    let to_pixels = |mils: u32, prop_id: u32| -> Result<i32> {
+        let dpi = props.read_i4(prop_id)?;
+        i32::try_from(mils as i64 * dpi as i64 / 1000)
+            .map_err(|_| WiaError::InvalidOption(format!("Page size {} is out of range", mils)))
+    };
+    let x_extent = to_pixels(width, WIA_IPS_XRES)?;
+    let y_extent = to_pixels(height, WIA_IPS_YRES)?;
+
+    validate_i4(props, WIA_IPS_XEXTENT, "Page width", x_extent)?;
+    validate_i4(props, WIA_IPS_YEXTENT, "Page height", y_extent)?;
+
+    props.write_i4(WIA_IPS_XEXTENT, x_extent)?;
+    props.write_i4(WIA_IPS_YEXTENT, y_extent)
+}
+
+/// Writes `value` to the first of two properties the driver accepts,
+/// returning which one that was, or the second one's error if neither did.
+fn write_either(
+    props: &impl ItemProperties,
+    [(first_id, first), (second_id, second)]: [(u32, RotationProperty); 2],
+    value: i32,
+) -> Result<RotationProperty> {
+    let write = |prop_id, property: RotationProperty| {
+        validate_i4(props, prop_id, &format!("{:?}", property), value)?;
+        props.write_i4(prop_id, value)
+    };
+
+    match write(first_id, first) {
+        Ok(()) => Ok(first),
+        Err(e) => {
+            log::debug!("Driver didn't accept {:?} {}, trying {:?}: {:?}", first, value, second, e);
+            write(second_id, second).map(|()| second)
+        }
+    }
+}
+
+/// Rotates the images a scan item produces, through `WIA_IPS_ROTATION`
+/// or, when the driver doesn't accept that, `WIA_IPS_ORIENTATION`.
+/// Returns the property that was written.
+pub fn set_rotation(item: &impl ItemProperties, rotation: Rotation) -> Result<RotationProperty> {
+    write_either(
+        item,
+        [
+            (WIA_IPS_ROTATION, RotationProperty::Rotation),
+            (WIA_IPS_ORIENTATION, RotationProperty::Orientation),
+        ],
+        rotation.wia_value() as i32,
+    )
+}
+
+/// Sets how pages are loaded, through `WIA_IPS_ORIENTATION` or, when the
+/// driver doesn't accept that, `WIA_IPS_ROTATION`. Returns the property
+/// that was written.
+pub fn set_orientation(
+    item: &impl ItemProperties,
+    orientation: Orientation,
+) -> Result<RotationProperty> {
+    write_either(
+        item,
+        [
+            (WIA_IPS_ORIENTATION, RotationProperty::Orientation),
+            (WIA_IPS_ROTATION, RotationProperty::Rotation),
+        ],
+        orientation.wia_value() as i32,
+    )
+}
+
+/// Restricts the scan to a rectangle of the bed, in pixels at the item's
+/// current resolution. Set the resolution first.
+///
+/// The rectangle is checked against the item's maximum scan size
+/// (`WIA_IPS_MAX_HORIZONTAL_SIZE`/`WIA_IPS_MAX_VERTICAL_SIZE`) when the
+/// driver reports it.
+pub fn set_scan_region(
+    item: &impl ItemProperties,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    if width == 0 || height == 0 {
+        return Err(WiaError::InvalidOption(
+            "Scan region must not be empty".to_string(),
+        ));
+    }
+
+    check_extent(item, WIA_IPS_MAX_HORIZONTAL_SIZE, WIA_IPS_XRES, "Horizontal", x, width)?;
+    check_extent(item, WIA_IPS_MAX_VERTICAL_SIZE, WIA_IPS_YRES, "Vertical", y, height)?;
+
+    let to_i4 = |value: u32| {
+        i32::try_from(value)
+            .map_err(|_| WiaError::InvalidOption(format!("Scan region value {} is out of range", value)))
+    };
+    let (x, y, width, height) = (to_i4(x)?, to_i4(y)?, to_i4(width)?, to_i4(height)?);
+
+    validate_i4(item, WIA_IPS_XPOS, "Horizontal position", x)?;
+    validate_i4(item, WIA_IPS_YPOS, "Vertical position", y)?;
+
+    item.write_i4(WIA_IPS_XPOS, x)?;
+    item.write_i4(WIA_IPS_YPOS, y)?;
+    item.write_i4(WIA_IPS_XEXTENT, width)?;
+    item.write_i4(WIA_IPS_YEXTENT, height)
+}
+
+/// Checks that `offset + extent` pixels fit in the bed along one axis. The
+/// maximum size is reported in thousandths of an inch, so it is converted
+/// with the axis' resolution.
+fn check_extent(
+    props: &impl ItemProperties,
+    max_size_prop: u32,
+    res_prop: u32,
+    axis: &str,
+    offset: u32,
+    extent: u32,
+) -> Result<()> {
+    let (Ok(max_size), Ok(dpi)) = (
+        props.read_i4(max_size_prop),
+        props.read_i4(res_prop),
+    ) else {
+        return Ok(());
+    };
+
+    let max_pixels = max_size.max(0) as u64 * dpi.max(0) as u64 / 1000;
+    if offset as u64 + extent as u64 > max_pixels {
+        return Err(WiaError::InvalidOption(format!(
+            "{} scan region {}+{} exceeds the bed size of {} pixels",
+            axis, offset, extent, max_pixels
+        )));
+    }
+
+    Ok(())
+}
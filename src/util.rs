@@ -1,12 +1,11 @@
-use std::mem::ManuallyDrop;
-
 use windows::{
     Win32::{
         Devices::ImageAcquisition::*,
         System::{
             Com::{
+                CoTaskMemFree,
                 StructuredStorage::{
-                    PROPSPEC, PROPSPEC_0, PROPVARIANT, PRSPEC_PROPID, PropVariantClear,
+                    PROPSPEC, PROPSPEC_0, PROPVARIANT, PRSPEC_PROPID, PropVariantClear, STATPROPSTG,
                 },
             },
             Variant::*,
@@ -15,194 +14,270 @@ use windows::{
     core::*,
 };
 
-pub(crate) fn read_bstr_property(prop_storage: &IWiaPropertyStorage, prop_id: u32) -> std::result::Result<String, String> {
+use crate::Result;
+use crate::WiaError;
+
+/// A property value read from an `IWiaPropertyStorage`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WiaValue {
+    Empty,
+    Str(String),
+    I4(i32),
+    Bool(bool),
+    Guid(GUID),
+    VecI4(Vec<i32>),
+}
+
+impl WiaValue {
+    /// The variant type the value is read from.
+    pub(crate) fn var_type(&self) -> u16 {
+        match self {
+            WiaValue::Empty => VT_EMPTY.0,
+            WiaValue::Str(_) => VT_BSTR.0,
+            WiaValue::I4(_) => VT_I4.0,
+            WiaValue::Bool(_) => VT_BOOL.0,
+            WiaValue::Guid(_) => VT_CLSID.0,
+            WiaValue::VecI4(_) => VT_VECTOR.0 | VT_I4.0,
+        }
+    }
+}
+
+/// Reads a property and converts it according to its variant type.
+pub fn read_property(prop_storage: &IWiaPropertyStorage, prop_id: u32) -> Result<WiaValue> {
     unsafe {
-        let mut property_id = PROPSPEC {
+        let property_id = PROPSPEC {
             ulKind: PRSPEC_PROPID,
             Anonymous: PROPSPEC_0 { propid: prop_id },
         };
         let mut property_variant = PROPVARIANT::default();
 
-        prop_storage.ReadMultiple(1, &mut property_id, &mut property_variant).map_err(|e| handle_error(e))?;
-
-        let result = if property_variant.vt() == VT_BSTR {
-            let bstr = ManuallyDrop::into_inner(
-                property_variant
-                    .Anonymous
-                    .Anonymous
-                    .Anonymous
-                    .bstrVal
-                    .clone(),
-            );
-            if !bstr.is_empty() {
-                let s = bstr.to_string();
-                // Don't free the BSTR - PropVariantClear will do that
-                std::mem::forget(bstr);
-                s
-            } else {
+        prop_storage.ReadMultiple(1, &property_id, &mut property_variant)?;
+
+        let result = variant_value(&property_variant);
+        PropVariantClear(&mut property_variant)?;
+        result
+    }
+}
+
+/// Converts a variant read from a property storage, leaving it untouched;
+/// the caller still owns it and has to clear it.
+fn variant_value(variant: &PROPVARIANT) -> Result<WiaValue> {
+    unsafe {
+        let value = &variant.Anonymous.Anonymous.Anonymous;
+        match variant.vt() {
+            VT_EMPTY => Ok(WiaValue::Empty),
+            // Copied out through a borrow, the string itself is freed when
+            // the caller clears the variant
+            VT_BSTR => Ok(WiaValue::Str(value.bstrVal.to_string())),
+            VT_I4 => Ok(WiaValue::I4(value.lVal)),
+            VT_BOOL => Ok(WiaValue::Bool(value.boolVal.as_bool())),
+            VT_CLSID if !value.puuid.is_null() => Ok(WiaValue::Guid(*value.puuid)),
+            vt if vt == VARENUM(VT_VECTOR.0 | VT_I4.0) => {
+                let cal = &value.cal;
+                if cal.pElems.is_null() {
+                    Ok(WiaValue::VecI4(Vec::new()))
+                } else {
+                    Ok(WiaValue::VecI4(
+                        std::slice::from_raw_parts(cal.pElems, cal.cElems as usize).to_vec(),
+                    ))
+                }
+            }
+            vt => Err(WiaError::UnsupportedPropertyType(vt.0)),
+        }
+    }
+}
+
+/// Lists every property a storage exposes as `(id, name, value)`, for
+/// finding out what a driver actually supports.
+///
+/// Properties whose value can't be read or converted are left out.
+pub fn enumerate_properties(prop_storage: &IWiaPropertyStorage) -> Result<Vec<(u32, String, WiaValue)>> {
+    let mut properties = Vec::new();
+
+    unsafe {
+        let enum_props = prop_storage.Enum()?;
+
+        loop {
+            let mut stat = [STATPROPSTG::default()];
+            let mut fetched = 0;
+            enum_props.Next(&mut stat, Some(&mut fetched)).ok()?;
+            if fetched == 0 {
+                break;
+            }
+
+            let [stat] = stat;
+            let name = if stat.lpwstrName.is_null() {
                 String::new()
+            } else {
+                let name = stat.lpwstrName.to_string().unwrap_or_default();
+                // The enumerator allocates the name for us to free
+                CoTaskMemFree(Some(stat.lpwstrName.0 as *const _));
+                name
+            };
+
+            match read_property(prop_storage, stat.propid) {
+                Ok(value) => properties.push((stat.propid, name, value)),
+                Err(e) => log::debug!("Skipping property {} ({}): {:?}", stat.propid, name, e),
             }
+        }
+    }
+
+    Ok(properties)
+}
+
+pub(crate) fn read_bstr_property(prop_storage: &IWiaPropertyStorage, prop_id: u32) -> Result<String> {
+    match read_property(prop_storage, prop_id)? {
+        WiaValue::Str(s) => Ok(s),
+        _ => Ok(String::new()),
+    }
+}
+
+/// Reads a string property as the raw UTF-16 the driver stored, for values
+/// such as device IDs that must not go through a lossy conversion.
+pub(crate) fn read_wide_property(prop_storage: &IWiaPropertyStorage, prop_id: u32) -> Result<Vec<u16>> {
+    unsafe {
+        let property_id = PROPSPEC {
+            ulKind: PRSPEC_PROPID,
+            Anonymous: PROPSPEC_0 { propid: prop_id },
+        };
+        let mut property_variant = PROPVARIANT::default();
+
+        prop_storage.ReadMultiple(1, &property_id, &mut property_variant)?;
+
+        let result = match property_variant.vt() {
+            VT_EMPTY => Ok(Vec::new()),
+            VT_BSTR => Ok(property_variant.Anonymous.Anonymous.Anonymous.bstrVal.to_vec()),
+            vt => Err(WiaError::UnsupportedPropertyType(vt.0)),
+        };
+
+        PropVariantClear(&mut property_variant)?;
+        result
+    }
+}
+
+/// Like [`read_bstr_property`], but `None` when the driver doesn't expose
+/// the property or leaves it empty.
+pub(crate) fn read_optional_bstr_property(prop_storage: &IWiaPropertyStorage, prop_id: u32) -> Option<String> {
+    match read_property(prop_storage, prop_id) {
+        Ok(WiaValue::Str(s)) if !s.is_empty() => Some(s),
+        _ => None,
+    }
+}
+
+pub(crate) fn read_i4_property(prop_storage: &IWiaPropertyStorage, prop_id: u32) -> Result<i32> {
+    unsafe {
+        let property_id = PROPSPEC {
+            ulKind: PRSPEC_PROPID,
+            Anonymous: PROPSPEC_0 { propid: prop_id },
+        };
+        let mut property_variant = PROPVARIANT::default();
+
+        prop_storage.ReadMultiple(1, &property_id, &mut property_variant)?;
+
+        let result = if property_variant.vt() == VT_I4 {
+            Ok(property_variant.Anonymous.Anonymous.Anonymous.lVal)
         } else {
-            String::new()
+            Err(WiaError::UnsupportedPropertyType(property_variant.vt().0))
+        };
+
+        PropVariantClear(&mut property_variant)?;
+        result
+    }
+}
+
+pub(crate) fn write_i4_property(prop_storage: &IWiaPropertyStorage, prop_id: u32, value: i32) -> Result<()> {
+    unsafe {
+        let property_id = PROPSPEC {
+            ulKind: PRSPEC_PROPID,
+            Anonymous: PROPSPEC_0 { propid: prop_id },
+        };
+        let mut property_variant = PROPVARIANT::default();
+        (*property_variant.Anonymous.Anonymous).Anonymous.lVal = value;
+        (*property_variant.Anonymous.Anonymous).vt = VT_I4;
+
+        prop_storage
+            .WriteMultiple(1, &property_id, &property_variant, WIA_IPA_FIRST)
+            .map_err(WiaError::from)
+    }
+}
+
+pub(crate) fn write_guid_property(prop_storage: &IWiaPropertyStorage, prop_id: u32, mut value: GUID) -> Result<()> {
+    unsafe {
+        let property_id = PROPSPEC {
+            ulKind: PRSPEC_PROPID,
+            Anonymous: PROPSPEC_0 { propid: prop_id },
         };
+        // The variant only borrows `value`, so it must not be cleared
+        let mut property_variant = PROPVARIANT::default();
+        (*property_variant.Anonymous.Anonymous).Anonymous.puuid = &mut value;
+        (*property_variant.Anonymous.Anonymous).vt = VT_CLSID;
 
-        PropVariantClear(&mut property_variant).map_err(|e| handle_error(e))?;
-        Ok(result)
+        prop_storage
+            .WriteMultiple(1, &property_id, &property_variant, WIA_IPA_FIRST)
+            .map_err(WiaError::from)
     }
 }
 
-const ERROR_CODES: [(&str, (&str, &str)); 20] = [
-    (
-        "0x80210006",
-        (
-            "WIA_ERROR_BUSY",
-            "The device is busy. Close any apps that are using this device or wait for it to finish and then try again.",
-        ),
-    ),
-    (
-        "0x80210016",
-        (
-            "WIA_ERROR_COVER_OPEN",
-            "One or more of the device’s cover is open.",
-        ),
-    ),
-    (
-        "0x8021000A",
-        (
-            "WIA_ERROR_DEVICE_COMMUNICATION",
-            "Communication with the WIA device failed. Make sure that the device is powered on and connected to the PC. If the problem persists, disconnect and reconnect the device.",
-        ),
-    ),
-    (
-        "0x8021000D",
-        (
-            "WIA_ERROR_DEVICE_LOCKED",
-            "The device is locked. Close any apps that are using this device or wait for it to finish and then try again.",
-        ),
-    ),
-    (
-        "0x8021000E",
-        (
-            "WIA_ERROR_EXCEPTION_IN_DRIVER",
-            "The device driver threw an exception.",
-        ),
-    ),
-    (
-        "0x80210001",
-        (
-            "WIA_ERROR_GENERAL_ERROR",
-            "An unknown error has occurred with the WIA device.",
-        ),
-    ),
-    (
-        "0x8021000C",
-        (
-            "WIA_ERROR_INCORRECT_HARDWARE_SETTING",
-            "There is an incorrect setting on the WIA device.",
-        ),
-    ),
-    (
-        "0x8021000B",
-        (
-            "WIA_ERROR_INVALID_COMMAND",
-            "The device doesn't support this command.",
-        ),
-    ),
-    (
-        "0x8021000F",
-        (
-            "WIA_ERROR_INVALID_DRIVER_RESPONSE",
-            "The response from the driver is invalid.",
-        ),
-    ),
-    (
-        "0x80210009",
-        (
-            "WIA_ERROR_ITEM_DELETED",
-            "The WIA device was deleted. It's no longer available.",
-        ),
-    ),
-    (
-        "0x80210017",
-        ("WIA_ERROR_LAMP_OFF", "The scanner's lamp is off."),
-    ),
-    (
-        "0x80210021",
-        (
-            "WIA_ERROR_MAXIMUM_PRINTER_ENDORSER_COUNTER",
-            "A scan job was interrupted because an Imprinter/Endorser item reached the maximum valid value for WIA_IPS_PRINTER_ENDORSER_COUNTER, and was reset to 0. This feature is available with Windows 8 and later versions of Windows.",
-        ),
-    ),
-    (
-        "0x80210020",
-        (
-            "WIA_ERROR_MULTI_FEED",
-            "A scan error occurred because of a multiple page feed condition. This feature is available with Windows 8 and later versions of Windows.",
-        ),
-    ),
-    (
-        "0x80210005",
-        (
-            "WIA_ERROR_OFFLINE",
-            "The device is offline. Make sure the device is powered on and connected to the PC.",
-        ),
-    ),
-    (
-        "0x80210003",
-        (
-            "WIA_ERROR_PAPER_EMPTY",
-            "There are no documents in the document feeder.",
-        ),
-    ),
-    (
-        "0x80210002",
-        (
-            "WIA_ERROR_PAPER_JAM",
-            "Paper is jammed in the scanner's document feeder.",
-        ),
-    ),
-    (
-        "0x80210004",
-        (
-            "WIA_ERROR_PAPER_PROBLEM",
-            "An unspecified problem occurred with the scanner's document feeder.",
-        ),
-    ),
-    (
-        "0x80210007",
-        ("WIA_ERROR_WARMING_UP", "The device is warming up."),
-    ),
-    (
-        "0x80210008",
-        (
-            "WIA_ERROR_USER_INTERVENTION",
-            "There is a problem with the WIA device. Make sure that the device is turned on, online, and any cables are properly connected.",
-        ),
-    ),
-    (
-        "0x80210015",
-        (
-            "WIA_S_NO_DEVICE_AVAILABLE",
-            "No scanner device was found. Make sure the device is online, connected to the PC, and has the correct driver installed on the PC.",
-        ),
-    ),
-];
-
-
-pub(crate) fn get_error(error_code: &str) -> Option<(&str, &str)> {
-    ERROR_CODES
-        .iter()
-        .find(|(code, _)| *code == error_code)
-        .map(|(_, (name, desc))| (*name, *desc))
+/// Reads the access flags of a property together with its valid values.
+///
+/// For `WIA_PROP_RANGE` properties the values are laid out as
+/// `[min, nominal, max, step]`, for `WIA_PROP_LIST` as
+/// `[count, nominal, values...]`. Properties that don't report their valid
+/// values as a vector of `VT_I4` come back with an empty list.
+pub(crate) fn read_property_attributes(
+    prop_storage: &IWiaPropertyStorage,
+    prop_id: u32,
+) -> Result<(u32, Vec<i32>)> {
+    unsafe {
+        let property_id = PROPSPEC {
+            ulKind: PRSPEC_PROPID,
+            Anonymous: PROPSPEC_0 { propid: prop_id },
+        };
+        let mut flags: u32 = 0;
+        let mut property_variant = PROPVARIANT::default();
+
+        prop_storage.GetPropertyAttributes(1, &property_id, &mut flags, &mut property_variant)?;
+
+        let values = if property_variant.vt() == VARENUM(VT_VECTOR.0 | VT_I4.0) {
+            let cal = &property_variant.Anonymous.Anonymous.Anonymous.cal;
+            if cal.pElems.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(cal.pElems, cal.cElems as usize).to_vec()
+            }
+        } else {
+            Vec::new()
+        };
+
+        PropVariantClear(&mut property_variant)?;
+        Ok((flags, values))
+    }
 }
 
-pub(crate) fn handle_error(err: Error) -> String {
-    let binding = err.code().to_string();
-    let code = binding.as_str();
-    let data = get_error(code);
-    if let Some((name, desc)) = data {
-        return format!("{} - {} - {}", code, name, desc);
-    } else {
-        return format!("Unknown error");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bstr_values_are_copied_not_taken() {
+        let variant = PROPVARIANT::from("Flatbed");
+
+        // Taking ownership of the string would free it on the first read
+        // and leave the variant dangling for the next one
+        for _ in 0..3 {
+            assert_eq!(variant_value(&variant), Ok(WiaValue::Str("Flatbed".into())));
+        }
+        assert_eq!(variant.vt(), VT_BSTR);
+    }
+
+    #[test]
+    fn converts_scalar_values() {
+        assert_eq!(variant_value(&PROPVARIANT::default()), Ok(WiaValue::Empty));
+        assert_eq!(variant_value(&PROPVARIANT::from(300i32)), Ok(WiaValue::I4(300)));
+        assert_eq!(variant_value(&PROPVARIANT::from(true)), Ok(WiaValue::Bool(true)));
+        assert_eq!(
+            variant_value(&PROPVARIANT::from(1.5f64)),
+            Err(WiaError::UnsupportedPropertyType(VT_R8.0))
+        );
     }
 }